@@ -0,0 +1,115 @@
+/* compares the rope backed TextBuffer with the Vec of rows the editor used before it.
+Run with `cargo bench`, BENCH_ROWS sets the size of the generated file */
+
+#[allow(dead_code)]
+#[path = "../src/buffer.rs"]
+mod buffer;
+
+use buffer::TextBuffer;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::env;
+use std::io::Cursor;
+
+fn sample_text() -> String {
+    let rows: usize = env::var("BENCH_ROWS")
+        .ok()
+        .and_then(|it| it.parse().ok())
+        .unwrap_or(200_000);
+    (0..rows)
+        .map(|i| {
+            format!(
+                "{}\tfn row_{}() -> usize {{ {} }} // some trailing text\n",
+                i, i, i
+            )
+        })
+        .collect()
+}
+
+// the old layout: every row owned, with its tab-expanded render kept next to it
+fn rows_from_text(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .map(|it| (it.to_string(), it.replace('\t', "        ")))
+        .collect()
+}
+
+fn bench_open(c: &mut Criterion, text: &str) {
+    let mut group = c.benchmark_group("open");
+    group.sample_size(10);
+    group.bench_function("rows", |b| b.iter(|| rows_from_text(text)));
+    group.bench_function("rope", |b| {
+        b.iter(|| TextBuffer::from_reader(Cursor::new(text)).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_insert_row(c: &mut Criterion, text: &str) {
+    let mut group = c.benchmark_group("insert row at the top");
+    let mut rows = rows_from_text(text);
+    group.bench_function("rows", |b| {
+        b.iter(|| {
+            rows.insert(1, (String::new(), String::new()));
+            rows.remove(1);
+        })
+    });
+    let mut buffer = TextBuffer::from_reader(Cursor::new(text)).unwrap();
+    group.bench_function("rope", |b| {
+        b.iter(|| {
+            buffer.insert_row(1, "");
+            buffer.remove_row(1);
+        })
+    });
+    group.finish();
+}
+
+fn bench_typing(c: &mut Criterion, text: &str) {
+    let mut group = c.benchmark_group("type in the middle");
+    let rows = rows_from_text(text);
+    let middle = rows.len() / 2;
+    group.bench_function("rows", |b| {
+        b.iter_batched_ref(
+            || rows[middle].clone(),
+            |(row, render)| {
+                for ch in "hello".chars() {
+                    row.insert(3, ch);
+                    *render = row.replace('\t', "        ");
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    let mut buffer = TextBuffer::from_reader(Cursor::new(text)).unwrap();
+    group.bench_function("rope", |b| {
+        b.iter(|| {
+            for ch in "hello".chars() {
+                buffer.insert(3, middle, &ch.to_string());
+            }
+            buffer.remove(3, middle, "hello");
+        })
+    });
+    group.finish();
+}
+
+fn bench_screen(c: &mut Criterion, text: &str) {
+    let mut group = c.benchmark_group("read a screen of rows");
+    let buffer = TextBuffer::from_reader(Cursor::new(text)).unwrap();
+    let middle = buffer.number_of_rows() / 2;
+    group.bench_function("rope", |b| {
+        b.iter(|| {
+            (middle..middle + 50)
+                .map(|at| buffer.row(at).len())
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+fn benches(c: &mut Criterion) {
+    let text = sample_text();
+    bench_open(c, &text);
+    bench_insert_row(c, &text);
+    bench_typing(c, &text);
+    bench_screen(c, &text);
+}
+
+criterion_group!(buffer_benches, benches);
+criterion_main!(buffer_benches);
@@ -0,0 +1,428 @@
+use ropey::Rope;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, BufRead, Read, Seek};
+
+// bytes looked at to tell the encoding, and whether the file is text at all
+const SNIFF_LEN: usize = 8192;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+        }
+    }
+}
+
+// the error from loading a file that is not text, see `is_binary_file`
+#[derive(Debug)]
+struct BinaryFile;
+
+impl fmt::Display for BinaryFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("binary file")
+    }
+}
+
+impl std::error::Error for BinaryFile {}
+
+pub fn is_binary_file(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<BinaryFile>())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> io::Result<String> {
+        let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+        let utf16 = |to_u16: fn([u8; 2]) -> u16| {
+            if !bytes.len().is_multiple_of(2) {
+                return Err(invalid("odd number of bytes in UTF-16 file".to_string()));
+            }
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|pair| to_u16([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16(&units).map_err(|err| invalid(err.to_string()))
+        };
+        match self {
+            Encoding::Utf8 => {
+                String::from_utf8(bytes.to_vec()).map_err(|err| invalid(err.to_string()))
+            }
+            Encoding::Utf16Le => utf16(u16::from_le_bytes),
+            Encoding::Utf16Be => utf16(u16::from_be_bytes),
+            Encoding::Latin1 => Ok(bytes.iter().map(|&byte| char::from(byte)).collect()),
+        }
+    }
+
+    fn encode(self, text: &str) -> io::Result<Cow<'_, [u8]>> {
+        Ok(match self {
+            Encoding::Utf8 => text.as_bytes().into(),
+            Encoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            Encoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            Encoding::Latin1 => text
+                .chars()
+                .map(|ch| {
+                    u8::try_from(ch).map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("'{}' can't be saved as Latin-1", ch),
+                        )
+                    })
+                })
+                .collect::<io::Result<Vec<u8>>>()?
+                .into(),
+        })
+    }
+}
+
+// how the file looked on disk, so saving writes it back the same way
+#[derive(Clone, Copy)]
+pub struct FileFormat {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    pub trailing_newline: bool,
+    pub bom: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Utf8,
+            line_ending: LineEnding::Lf,
+            trailing_newline: true,
+            bom: false,
+        }
+    }
+}
+
+impl FileFormat {
+    // e.g. "UTF-8 CRLF BOM noeol", shown in the status bar
+    pub fn describe(&self) -> String {
+        let mut description = format!("{} {}", self.encoding.name(), self.line_ending.name());
+        // UTF-16 is only recognised by its BOM, so it always has one
+        if self.bom && self.encoding == Encoding::Utf8 {
+            description.push_str(" BOM");
+        }
+        if !self.trailing_newline {
+            description.push_str(" noeol");
+        }
+        description
+    }
+}
+
+/* the text of a file kept in a rope, rows joined by '\n'. Positions are a row index plus
+a byte offset inside that row, the same as the cursor. An empty rope is either a buffer
+with no rows at all or one holding a single empty row, `no_rows` tells them apart */
+pub struct TextBuffer {
+    rope: Rope,
+    no_rows: bool,
+    pub format: FileFormat,
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        Self {
+            rope: Rope::new(),
+            no_rows: true,
+            format: FileFormat::default(),
+        }
+    }
+
+    /* UTF-16 is recognised by its BOM, anything else is read as UTF-8 and, when that
+    fails, as Latin-1. NUL bytes at the start mean a binary file, which is an error */
+    pub fn from_reader(reader: impl Read + Seek) -> io::Result<Self> {
+        let mut reader = io::BufReader::with_capacity(SNIFF_LEN, reader);
+        let head = reader.fill_buf()?;
+        let encoding = match head {
+            [0xff, 0xfe, ..] => Encoding::Utf16Le,
+            [0xfe, 0xff, ..] => Encoding::Utf16Be,
+            _ if head.contains(&0) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, BinaryFile))
+            }
+            _ => Encoding::Utf8,
+        };
+        // UTF-8 files are streamed, the rest is small enough to decode in one go
+        if encoding == Encoding::Utf8 {
+            match Rope::from_reader(&mut reader) {
+                Ok(rope) => return Ok(Self::from_rope(rope, encoding)),
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let mut reader = reader.into_inner();
+        reader.rewind()?;
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let encoding = match encoding {
+            Encoding::Utf8 => Encoding::Latin1,
+            encoding => encoding,
+        };
+        Ok(Self::from_rope(
+            Rope::from_str(&encoding.decode(&bytes)?),
+            encoding,
+        ))
+    }
+
+    /* splits the text into rows on "\n" and "\r\n", a final line ending does not start
+    another row. The first line ending sets the style the whole file is saved with, a BOM
+    and the final line ending are dropped from the text and kept in `format` */
+    fn from_rope(mut rope: Rope, encoding: Encoding) -> Self {
+        if rope.len_chars() == 0 {
+            return Self::new();
+        }
+        let bom = rope.char(0) == '\u{feff}';
+        if bom {
+            rope.remove(0..1);
+        }
+        let first_line = rope.line(0);
+        let line_ending = match first_line.len_chars() {
+            len if len >= 2 && first_line.slice(len - 2..) == "\r\n" => LineEnding::Crlf,
+            _ => LineEnding::Lf,
+        };
+        if rope.chunks().any(|chunk| chunk.contains('\r')) {
+            rope = Rope::from_str(&rope.to_string().replace("\r\n", "\n"));
+        }
+        let len = rope.len_chars();
+        let trailing_newline = len > 0 && rope.char(len - 1) == '\n';
+        if trailing_newline {
+            rope.remove(len - 1..len);
+        }
+        Self {
+            rope,
+            no_rows: false,
+            format: FileFormat {
+                encoding,
+                line_ending,
+                trailing_newline,
+                bom,
+            },
+        }
+    }
+
+    pub fn number_of_rows(&self) -> usize {
+        if self.no_rows {
+            0
+        } else {
+            self.rope.len_lines()
+        }
+    }
+
+    // borrowed straight from the rope unless the row spans several chunks
+    pub fn row(&self, at: usize) -> Cow<'_, str> {
+        let line = self.rope.line(at);
+        if at + 1 < self.rope.len_lines() {
+            line.slice(..line.len_chars() - 1).into()
+        } else {
+            line.into()
+        }
+    }
+
+    fn char_index(&self, x: usize, y: usize) -> usize {
+        self.rope.line_to_char(y) + self.rope.line(y).byte_to_char(x)
+    }
+
+    pub fn insert_row(&mut self, at: usize, contents: &str) {
+        if self.no_rows {
+            self.no_rows = false;
+            self.rope.insert(0, contents);
+        } else if at == self.number_of_rows() {
+            let end = self.rope.len_chars();
+            self.rope.insert(end, &format!("\n{}", contents));
+        } else {
+            let start = self.rope.line_to_char(at);
+            self.rope.insert(start, &format!("{}\n", contents));
+        }
+    }
+
+    pub fn remove_row(&mut self, at: usize) {
+        let number_of_rows = self.number_of_rows();
+        if number_of_rows == 1 {
+            self.rope = Rope::new();
+            self.no_rows = true;
+        } else if at + 1 == number_of_rows {
+            // the last row takes the line ending in front of it along
+            let start = self.rope.line_to_char(at) - 1;
+            self.rope.remove(start..);
+        } else {
+            let start = self.rope.line_to_char(at);
+            let end = self.rope.line_to_char(at + 1);
+            self.rope.remove(start..end);
+        }
+    }
+
+    // `text` may hold '\n' and so split the row
+    pub fn insert(&mut self, x: usize, y: usize, text: &str) {
+        let at = self.char_index(x, y);
+        self.rope.insert(at, text);
+    }
+
+    // removes `text`, which must be what the buffer holds at (x, y)
+    pub fn remove(&mut self, x: usize, y: usize, text: &str) {
+        let at = self.char_index(x, y);
+        self.rope.remove(at..at + text.chars().count());
+    }
+
+    // the text from (x, y) up to (end_x, end_y), rows joined by '\n'
+    pub fn slice(&self, (x, y): (usize, usize), (end_x, end_y): (usize, usize)) -> String {
+        let start = self.char_index(x, y);
+        let end = self.char_index(end_x, end_y);
+        self.rope.slice(start..end).to_string()
+    }
+
+    // rows joined by the line ending and encoded as `format` says, returns the bytes written
+    pub fn write_to(&self, writer: &mut impl io::Write) -> io::Result<usize> {
+        let mut len = 0;
+        let encoding = self.format.encoding;
+        let mut write = |text: &str| {
+            let bytes = encoding.encode(text)?;
+            len += bytes.len();
+            writer.write_all(&bytes)
+        };
+        if self.no_rows {
+            return Ok(0);
+        }
+        let line_ending = self.format.line_ending.as_str();
+        if self.format.bom {
+            write("\u{feff}")?;
+        }
+        for chunk in self.rope.chunks() {
+            let mut lines = chunk.split('\n');
+            write(lines.next().unwrap_or_default())?;
+            for line in lines {
+                write(line_ending)?;
+                write(line)?;
+            }
+        }
+        if self.format.trailing_newline {
+            write(line_ending)?;
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn load(bytes: &[u8]) -> TextBuffer {
+        TextBuffer::from_reader(Cursor::new(bytes.to_vec())).unwrap()
+    }
+
+    fn write(buffer: &TextBuffer) -> Vec<u8> {
+        let mut written = Vec::new();
+        let len = buffer.write_to(&mut written).unwrap();
+        assert_eq!(len, written.len());
+        written
+    }
+
+    fn rows(buffer: &TextBuffer) -> Vec<String> {
+        (0..buffer.number_of_rows())
+            .map(|at| buffer.row(at).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_line_endings_and_bom() {
+        let files: &[&[u8]] = &[
+            b"",
+            b"\n",
+            b"\n\n",
+            b"one\ntwo\n",
+            b"one\ntwo",
+            b"one\r\ntwo\r\n",
+            b"one\r\ntwo",
+            b"\r\n",
+            b"\xef\xbb\xbfone\ntwo\n",
+            b"\xef\xbb\xbfone\r\ntwo",
+            b"\xef\xbb\xbf",
+        ];
+        for bytes in files {
+            assert_eq!(write(&load(bytes)), *bytes, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn splits_rows_without_line_endings() {
+        let buffer = load(b"\xef\xbb\xbfone\r\ntwo\r\n");
+        assert_eq!(rows(&buffer), ["one", "two"]);
+        assert!(buffer.format.bom);
+        assert!(buffer.format.trailing_newline);
+        assert!(buffer.format.line_ending == LineEnding::Crlf);
+        assert_eq!(rows(&load(b"")), Vec::<String>::new());
+        assert_eq!(rows(&load(b"\n")), [""]);
+        assert!(!load(b"one").format.trailing_newline);
+    }
+
+    #[test]
+    fn mixed_line_endings_follow_the_first_line() {
+        assert_eq!(write(&load(b"a\r\nb\nc\r\n")), b"a\r\nb\r\nc\r\n");
+        assert_eq!(write(&load(b"a\nb\r\nc")), b"a\nb\nc");
+    }
+
+    #[test]
+    fn round_trips_other_encodings() {
+        let files: &[(&[u8], Encoding)] = &[
+            (b"caf\xe9\r\nna\xefve", Encoding::Latin1),
+            (b"\xff\xfea\x00\xe9\x00\r\x00\n\x00", Encoding::Utf16Le),
+            (b"\xfe\xff\x00a\x00\n\x00b", Encoding::Utf16Be),
+            (b"\xff\xfe", Encoding::Utf16Le),
+        ];
+        for (bytes, encoding) in files {
+            let buffer = load(bytes);
+            assert!(buffer.format.encoding == *encoding, "{:?}", bytes);
+            assert_eq!(write(&buffer), *bytes, "{:?}", bytes);
+        }
+        assert_eq!(rows(&load(b"caf\xe9")), ["café"]);
+        assert_eq!(rows(&load(b"\xff\xfea\x00\n\x00b\x00")), ["a", "b"]);
+    }
+
+    #[test]
+    fn refuses_what_it_can_not_read_or_write() {
+        let err = TextBuffer::from_reader(Cursor::new(b"\x7fELF\x00\x01".to_vec())).err();
+        assert!(err.is_some_and(|err| is_binary_file(&err)));
+        let err = TextBuffer::from_reader(Cursor::new(b"\xff\xfea".to_vec())).err();
+        assert!(err.is_some_and(|err| err.kind() == io::ErrorKind::InvalidData));
+        let mut buffer = load(b"caf\xe9");
+        buffer.insert(0, 0, "€");
+        assert!(buffer.write_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn edits_are_saved_in_the_file_style() {
+        let mut buffer = load(b"one\r\nthree");
+        buffer.insert_row(1, "two");
+        buffer.insert(5, 2, "\nfour");
+        assert_eq!(write(&buffer), b"one\r\ntwo\r\nthree\r\nfour");
+    }
+}
@@ -0,0 +1,214 @@
+use crate::history::History;
+use crate::keymap::Command;
+use crate::{CursorController, EditorRows, Output};
+use crossterm::event::*;
+use std::path::{Path, PathBuf};
+use std::{fs, mem};
+use unicode_width::UnicodeWidthStr;
+
+/* a file open in the background. The buffer on screen lives in Output's own fields and
+is swapped with one of these when switching */
+pub struct Buffer {
+    editor_rows: EditorRows,
+    cursor_controller: CursorController,
+    dirty: u64,
+    history: History,
+}
+
+impl Buffer {
+    pub fn new(editor_rows: EditorRows, win_size: (usize, usize), soft_wrap: bool) -> Self {
+        let mut cursor_controller = CursorController::new(win_size);
+        cursor_controller.soft_wrap = soft_wrap;
+        Self {
+            editor_rows,
+            cursor_controller,
+            dirty: 0,
+            history: History::new(),
+        }
+    }
+}
+
+// `path` with symlinks, "." and ".." resolved, as given when the file doesn't exist yet
+fn resolved(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl Output {
+    pub(crate) fn number_of_buffers(&self) -> usize {
+        self.buffers.len() + 1
+    }
+
+    // runs `f` on the rows and cursor of every buffer, the one on screen first
+    pub(crate) fn for_each_buffer(
+        &mut self,
+        mut f: impl FnMut(&mut EditorRows, &mut CursorController),
+    ) {
+        f(&mut self.editor_rows, &mut self.cursor_controller);
+        for buffer in &mut self.buffers {
+            f(&mut buffer.editor_rows, &mut buffer.cursor_controller);
+        }
+    }
+
+    // puts `buffer` on screen and hands back the one that was there
+    fn swap_buffer(&mut self, mut buffer: Buffer) -> Buffer {
+        mem::swap(&mut self.editor_rows, &mut buffer.editor_rows);
+        mem::swap(&mut self.cursor_controller, &mut buffer.cursor_controller);
+        mem::swap(&mut self.dirty, &mut buffer.dirty);
+        mem::swap(&mut self.history, &mut buffer.history);
+        // the terminal may have been resized while the buffer was in the background
+        self.cursor_controller.screen_columns = self.win_size.0;
+        self.cursor_controller.screen_rows = self.win_size.1;
+        self.search_index.highlight = None;
+        buffer
+    }
+
+    /* adds a buffer after the others and switches to it. The empty buffer the editor
+    starts with when no file is given is replaced instead */
+    pub(crate) fn open_buffer(&mut self, editor_rows: EditorRows) {
+        if self.editor_rows.filename.is_none()
+            && self.editor_rows.number_of_rows() == 0
+            && self.dirty == 0
+        {
+            self.swap_buffer(Buffer::new(editor_rows, self.win_size, self.settings.wrap));
+            return;
+        }
+        let previous =
+            self.swap_buffer(Buffer::new(editor_rows, self.win_size, self.settings.wrap));
+        self.buffers.insert(self.current_buffer, previous);
+        self.current_buffer = self.buffers.len();
+    }
+
+    /* the buffer holding `path`, if it is open already. Paths are compared resolved, so
+    "./foo" and an absolute path find the same buffer as "foo" */
+    pub(crate) fn find_buffer(&self, path: &Path) -> Option<usize> {
+        let path = resolved(path);
+        let same = |editor_rows: &EditorRows| {
+            editor_rows
+                .filename
+                .as_deref()
+                .is_some_and(|it| resolved(it) == path)
+        };
+        if same(&self.editor_rows) {
+            return Some(self.current_buffer);
+        }
+        self.buffers
+            .iter()
+            .position(|it| same(&it.editor_rows))
+            .map(|at| if at < self.current_buffer { at } else { at + 1 })
+    }
+
+    pub(crate) fn switch_buffer(&mut self, to: usize) {
+        if to == self.current_buffer || to >= self.number_of_buffers() {
+            return;
+        }
+        // `buffers` skips the current one, so indexes past it are shifted down by one
+        let next = self
+            .buffers
+            .remove(if to > self.current_buffer { to - 1 } else { to });
+        let previous = self.swap_buffer(next);
+        self.buffers.insert(
+            if to > self.current_buffer {
+                self.current_buffer
+            } else {
+                self.current_buffer - 1
+            },
+            previous,
+        );
+        self.current_buffer = to;
+    }
+
+    pub(crate) fn next_buffer(&mut self) {
+        self.switch_buffer((self.current_buffer + 1) % self.number_of_buffers());
+    }
+
+    pub(crate) fn previous_buffer(&mut self) {
+        let number_of_buffers = self.number_of_buffers();
+        self.switch_buffer((self.current_buffer + number_of_buffers - 1) % number_of_buffers);
+    }
+
+    pub(crate) fn unsaved_buffers(&self) -> usize {
+        self.buffers.iter().filter(|it| it.dirty > 0).count() + usize::from(self.dirty > 0)
+    }
+
+    // name and modified flag of every buffer, in order
+    fn buffer_names(&self) -> Vec<String> {
+        let name = |editor_rows: &EditorRows, dirty: u64| {
+            format!(
+                "{}{}",
+                editor_rows.file_name(),
+                if dirty > 0 { "*" } else { "" }
+            )
+        };
+        let mut names: Vec<String> = self
+            .buffers
+            .iter()
+            .map(|it| name(&it.editor_rows, it.dirty))
+            .collect();
+        names.insert(self.current_buffer, name(&self.editor_rows, self.dirty));
+        names
+    }
+
+    pub(crate) fn pick_buffer(&mut self) -> crossterm::Result<()> {
+        let names = self.buffer_names();
+        if let Some(at) = self.pick("Buffers", &names, self.current_buffer)? {
+            self.switch_buffer(at);
+        }
+        Ok(())
+    }
+
+    // lists `names` in the message bar, arrows move the selection and Enter picks it
+    pub(crate) fn pick(
+        &mut self,
+        title: &str,
+        names: &[String],
+        mut selected: usize,
+    ) -> crossterm::Result<Option<usize>> {
+        let picked = loop {
+            let items: Vec<String> = names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    if i == selected {
+                        format!("[{} {}]", i + 1, name)
+                    } else {
+                        format!(" {} {} ", i + 1, name)
+                    }
+                })
+                .collect();
+            // leaves out items at the front until the selected one fits on the line
+            let mut first = 0;
+            while first < selected
+                && items[first..=selected]
+                    .iter()
+                    .map(|it| it.width())
+                    .sum::<usize>()
+                    > self.win_size.0.saturating_sub(title.width() + 3)
+            {
+                first += 1;
+            }
+            self.status_message
+                .set_message(format!("{}:{}", title, items[first..].concat()));
+            self.refresh_screen()?;
+            let key_event = self.read_key()?;
+            match (self.keymap.command(key_event), key_event.code) {
+                (Some(Command::MoveLeft | Command::MoveUp), _) => {
+                    selected = (selected + names.len() - 1) % names.len()
+                }
+                (Some(Command::MoveRight | Command::MoveDown | Command::InsertTab), _) => {
+                    selected = (selected + 1) % names.len()
+                }
+                (Some(Command::Newline), _) => break Some(selected),
+                (Some(Command::Cancel), _) => break None,
+                (_, KeyCode::Char(ch @ '1'..='9')) => {
+                    let at = ch as usize - '1' as usize;
+                    if at < names.len() {
+                        selected = at
+                    }
+                }
+                _ => {}
+            }
+        };
+        self.status_message.set_message(String::new());
+        Ok(picked)
+    }
+}
@@ -0,0 +1,328 @@
+use crate::history::EditOp;
+use crate::selection::position_after;
+use crate::Output;
+use std::env;
+use std::io::{self, stdout, Write};
+use std::process::{Command, Stdio};
+
+// entries kept for Alt-V, the oldest is dropped first
+const KILL_RING_SIZE: usize = 16;
+// characters of an entry shown when picking one to paste
+const PREVIEW_LEN: usize = 20;
+
+// cut or copied text. Whole lines are pasted above the cursor row instead of inside it
+#[derive(Clone)]
+pub(crate) struct Clip {
+    pub(crate) text: String,
+    pub(crate) lines: bool,
+}
+
+// how copied text reaches the system clipboard, the `clipboard` setting
+pub enum SystemClipboard {
+    // a clipboard command when one is installed, OSC 52 otherwise
+    Auto,
+    Off,
+    // the terminal sets the clipboard when sent an OSC 52 escape sequence
+    Osc52,
+    // reads the text on stdin, e.g. "xclip -selection clipboard"
+    Command(String),
+}
+
+impl SystemClipboard {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "auto" => SystemClipboard::Auto,
+            "off" | "none" => SystemClipboard::Off,
+            "osc52" => SystemClipboard::Osc52,
+            command => SystemClipboard::Command(command.into()),
+        }
+    }
+}
+
+fn on_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+// the clipboard command of the running desktop, if it is installed
+fn detect_command() -> Option<&'static str> {
+    if env::var_os("WAYLAND_DISPLAY").is_some() && on_path("wl-copy") {
+        Some("wl-copy")
+    } else if env::var_os("DISPLAY").is_some() && on_path("xclip") {
+        Some("xclip -selection clipboard")
+    } else if on_path("pbcopy") {
+        Some("pbcopy")
+    } else {
+        None
+    }
+}
+
+fn run_command(command: &str, text: &str) -> io::Result<()> {
+    // anything the command prints would end up on top of the editor
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // stdin is dropped after writing, which closes it and ends the text
+    let written = child
+        .stdin
+        .take()
+        .map_or(Ok(()), |mut stdin| stdin.write_all(text.as_bytes()));
+    let status = child.wait()?;
+    written?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("\"{}\" {}", command, status)))
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | u32::from(*byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(ALPHABET[(bits >> (18 - 6 * i)) as usize & 63]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn osc52(text: &str) -> io::Result<()> {
+    write!(stdout(), "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stdout().flush()
+}
+
+/* the kill ring, newest entry last, and where copies go outside the editor. Terminals
+that don't know OSC 52 ignore it, so it is the fallback when no command works */
+pub struct Clipboard {
+    ring: Vec<Clip>,
+    system: SystemClipboard,
+}
+
+impl Clipboard {
+    pub fn new(system: SystemClipboard) -> Self {
+        Self {
+            ring: Vec::new(),
+            system,
+        }
+    }
+
+    pub(crate) fn set_system(&mut self, system: SystemClipboard) {
+        self.system = system;
+    }
+
+    pub(crate) fn push(&mut self, clip: Clip) {
+        if self.ring.last().is_some_and(|last| last.text == clip.text) {
+            return;
+        }
+        self.ring.push(clip);
+        if self.ring.len() > KILL_RING_SIZE {
+            self.ring.remove(0);
+        }
+    }
+
+    pub(crate) fn last(&self) -> Option<&Clip> {
+        self.ring.last()
+    }
+
+    // hands `text` to the system clipboard, returns why the configured way didn't work
+    fn sync(&self, text: &str) -> Option<String> {
+        let result = match &self.system {
+            SystemClipboard::Off => Ok(()),
+            SystemClipboard::Osc52 => osc52(text),
+            SystemClipboard::Auto => match detect_command() {
+                Some(command) => run_command(command, text).or_else(|_| osc52(text)),
+                None => osc52(text),
+            },
+            SystemClipboard::Command(command) => {
+                return run_command(command, text)
+                    .err()
+                    .map(|err| match osc52(text) {
+                        Ok(()) => format!("{}, sent with OSC 52 instead", err),
+                        Err(_) => err.to_string(),
+                    })
+            }
+        };
+        result.err().map(|err| err.to_string())
+    }
+}
+
+impl Clip {
+    // the first line, shortened, for the list Alt-V shows
+    fn preview(&self) -> String {
+        let first_line = self.text.lines().next().unwrap_or_default();
+        let mut preview: String = first_line.chars().take(PREVIEW_LEN).collect();
+        if preview.len() < self.text.trim_end_matches('\n').len() {
+            preview.push_str("...");
+        }
+        preview
+    }
+}
+
+impl Output {
+    // the selection, or the cursor row as a whole line
+    fn clip_at_cursor(&self) -> Option<Clip> {
+        if let Some((start, end)) = self.cursor_controller.selection(&self.editor_rows) {
+            return Some(Clip {
+                text: self.editor_rows.buffer.slice(start, end),
+                lines: false,
+            });
+        }
+        let y = self.cursor_controller.cursor_y;
+        (y < self.editor_rows.number_of_rows()).then(|| Clip {
+            text: format!("{}\n", self.editor_rows.get_row(y)),
+            lines: true,
+        })
+    }
+
+    pub(crate) fn keep_clip(&mut self, clip: Clip, verb: &str) {
+        let what = if clip.lines {
+            match clip.text.matches('\n').count() {
+                1 => "1 line".to_string(),
+                lines => format!("{} lines", lines),
+            }
+        } else {
+            format!("{} characters", clip.text.chars().count())
+        };
+        let failure = self.clipboard.sync(&clip.text);
+        self.clipboard.push(clip);
+        self.status_message.set_message(match failure {
+            None => format!("{} {}", verb, what),
+            Some(failure) => format!("{} {} (system clipboard: {})", verb, what, failure),
+        });
+    }
+
+    pub(crate) fn copy(&mut self) {
+        if let Some(clip) = self.clip_at_cursor() {
+            self.keep_clip(clip, "Copied")
+        }
+    }
+
+    pub(crate) fn cut(&mut self) {
+        let clip = match self.clip_at_cursor() {
+            None => return,
+            Some(clip) => clip,
+        };
+        let lines = clip.lines;
+        self.keep_clip(clip, "Cut");
+        if lines {
+            self.remove_cursor_row()
+        } else {
+            self.delete_selection()
+        }
+    }
+
+    // takes out the cursor row along with its line ending, as one undo step
+    fn remove_cursor_row(&mut self) {
+        let before = self.cursor_controller.position();
+        let y = before.1;
+        let row = self.editor_rows.get_row(y).to_string();
+        let op = if y + 1 < self.editor_rows.number_of_rows() {
+            EditOp::Delete {
+                x: 0,
+                y,
+                text: format!("{}\n", row),
+            }
+        } else if y > 0 {
+            EditOp::Delete {
+                x: self.editor_rows.get_row(y - 1).len(),
+                y: y - 1,
+                text: format!("\n{}", row),
+            }
+        } else {
+            EditOp::Delete { x: 0, y, text: row }
+        };
+        self.editor_rows.apply(&op);
+        let after = (
+            0,
+            y.min(self.editor_rows.number_of_rows().saturating_sub(1)),
+        );
+        self.cursor_controller.set_position(after);
+        self.history.record_all(vec![op], before, after);
+        self.dirty += 1;
+    }
+
+    /* puts `text` at the cursor, or in place of the selection, as one undo step. The text
+    may span several rows */
+    pub(crate) fn insert_text(&mut self, text: &str) {
+        if text.is_empty() || self.replace_selection(text) {
+            return;
+        }
+        let before = self.cursor_controller.position();
+        let mut ops = Vec::new();
+        if before.1 == self.editor_rows.number_of_rows() {
+            ops.push(EditOp::InsertRow { y: before.1 });
+        }
+        ops.push(EditOp::Insert {
+            x: before.0,
+            y: before.1,
+            text: text.into(),
+        });
+        ops.iter().for_each(|op| self.editor_rows.apply(op));
+        let after = position_after(before, text);
+        self.cursor_controller.set_position(after);
+        self.history.record_all(ops, before, after);
+        self.dirty += 1;
+    }
+
+    // text pasted into the terminal, whose line breaks usually arrive as "\r"
+    pub(crate) fn paste_text(&mut self, text: &str) {
+        if self.editor_rows.hex.is_some() {
+            self.status_message
+                .set_message("Can't paste text into the hex view".into());
+            return;
+        }
+        self.insert_text(&text.replace("\r\n", "\n").replace('\r', "\n"));
+    }
+
+    pub(crate) fn paste_clip(&mut self, clip: Clip) {
+        let y = self.cursor_controller.cursor_y;
+        if !clip.lines || self.has_selection() {
+            self.insert_text(&clip.text);
+        } else if y < self.editor_rows.number_of_rows() {
+            // whole lines go in above the cursor row
+            self.cursor_controller.set_position((0, y));
+            self.insert_text(&clip.text);
+        } else {
+            self.insert_text(clip.text.strip_suffix('\n').unwrap_or(&clip.text));
+        }
+    }
+
+    pub(crate) fn paste(&mut self) {
+        match self.clipboard.ring.last() {
+            None => self.status_message.set_message("Nothing to paste".into()),
+            Some(clip) => self.paste_clip(clip.clone()),
+        }
+    }
+
+    // picks one of the earlier cuts and copies to paste, the newest first
+    pub(crate) fn paste_from_ring(&mut self) -> crossterm::Result<()> {
+        let names: Vec<String> = self
+            .clipboard
+            .ring
+            .iter()
+            .rev()
+            .map(Clip::preview)
+            .collect();
+        if names.is_empty() {
+            self.status_message.set_message("Nothing to paste".into());
+            return Ok(());
+        }
+        if let Some(at) = self.pick("Paste", &names, 0)? {
+            let clip = self.clipboard.ring[names.len() - 1 - at].clone();
+            self.paste_clip(clip);
+        }
+        Ok(())
+    }
+}
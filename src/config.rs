@@ -0,0 +1,17 @@
+use std::env;
+use std::path::PathBuf;
+
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+// `$XDG_CONFIG_HOME/juspay`, falling back to `~/.config/juspay`
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".config")))
+        .map(|dir| dir.join("juspay"))
+}
@@ -0,0 +1,191 @@
+use crate::{prompt, CursorController, EditorRows, Output, Reader};
+use crossterm::event::*;
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+
+// a line counted from 1 and maybe a column, also counted from 1 in characters
+pub type Position = (usize, Option<usize>);
+
+// "12" or "12:5"
+fn parse_line_column(input: &str) -> Option<Position> {
+    let (line, column) = match input.split_once(':') {
+        None => (input, None),
+        Some((line, column)) => (line, Some(column.trim().parse().ok()?)),
+    };
+    Some((line.trim().parse().ok()?, column))
+}
+
+/* what the Ctrl-G prompt accepts: "line", "line:column", "+N" and "-N" relative to the
+cursor row, or "N%" of the file. Returns the row to go to, counted from 0 */
+fn parse_goto(
+    input: &str,
+    cursor_y: usize,
+    number_of_rows: usize,
+) -> Option<(usize, Option<usize>)> {
+    let input = input.trim();
+    if let Some(percent) = input.strip_suffix('%') {
+        let percent: usize = percent.trim().parse().ok()?;
+        return Some((number_of_rows * percent.min(100) / 100, None));
+    }
+    if let Some(lines) = input.strip_prefix('+') {
+        return Some((cursor_y.saturating_add(lines.trim().parse().ok()?), None));
+    }
+    if let Some(lines) = input.strip_prefix('-') {
+        return Some((cursor_y.saturating_sub(lines.trim().parse().ok()?), None));
+    }
+    let (line, column) = parse_line_column(input)?;
+    Some((line.saturating_sub(1), column))
+}
+
+// "file:12" -> ("file", 12)
+fn split_number(text: &str) -> Option<(&str, usize)> {
+    let (rest, number) = text.rsplit_once(':')?;
+    if rest.is_empty() {
+        return None;
+    }
+    Some((rest, number.parse().ok()?))
+}
+
+/* "src/main.rs:12:5" as compilers print it, split into the file and the position. An
+argument naming a file that exists is never split, even when it ends in ":N" */
+pub fn split_position(arg: &str) -> (&str, Option<Position>) {
+    if Path::new(arg).exists() {
+        return (arg, None);
+    }
+    // grep -n leaves a ':' after the line number
+    let trimmed = arg.strip_suffix(':').unwrap_or(arg);
+    match split_number(trimmed) {
+        None => (arg, None),
+        Some((rest, last)) => match split_number(rest) {
+            Some((path, line)) => (path, Some((line, Some(last)))),
+            None => (rest, Some((last, None))),
+        },
+    }
+}
+
+impl CursorController {
+    // puts row `y` in the middle of the screen, as far as the file allows
+    fn center_on(&mut self, y: usize) {
+        self.row_offset = y.saturating_sub(self.screen_rows / 2);
+        self.line_offset = 0;
+    }
+
+    /* moves to row `y` counted from 0, and to `column` counted from 1 in characters like
+    compilers count them. Rows and columns past the end stop at the end. Like any jump it
+    drops the selection */
+    pub(crate) fn go_to(&mut self, y: usize, column: Option<usize>, editor_rows: &EditorRows) {
+        let y = y.min(editor_rows.number_of_rows().saturating_sub(1));
+        let x = match column {
+            Some(column) if y < editor_rows.number_of_rows() => {
+                let row = editor_rows.get_row(y);
+                let mut chars = 0;
+                row.grapheme_indices(true)
+                    .find(|(_, grapheme)| {
+                        chars += grapheme.chars().count();
+                        chars >= column
+                    })
+                    .map_or(row.len(), |(at, _)| at)
+            }
+            _ => 0,
+        };
+        self.set_position((x, y));
+        self.center_on(y);
+    }
+}
+
+impl Output {
+    pub(crate) fn go_to_prompt(&mut self) -> crossterm::Result<()> {
+        let input = match prompt!(
+            self,
+            "Go to: {} (line, line:column, +N, -N or N%, ESC to cancel)"
+        ) {
+            None => return Ok(()),
+            Some(input) => input,
+        };
+        let number_of_rows = self.editor_rows.number_of_rows();
+        match parse_goto(&input, self.cursor_controller.cursor_y, number_of_rows) {
+            None => self
+                .status_message
+                .set_message(format!("Not a line number: \"{}\"", input)),
+            Some((y, column)) => {
+                self.history.seal();
+                self.cursor_controller.go_to(y, column, &self.editor_rows);
+            }
+        }
+        Ok(())
+    }
+
+    // jumps to a position given on the command line, lines counted from 1
+    pub(crate) fn go_to_position(&mut self, (line, column): Position) {
+        self.cursor_controller
+            .go_to(line.saturating_sub(1), column, &self.editor_rows);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_what_the_prompt_accepts() {
+        assert_eq!(parse_goto("12", 0, 100), Some((11, None)));
+        assert_eq!(parse_goto(" 12:5 ", 0, 100), Some((11, Some(5))));
+        assert_eq!(parse_goto("0", 0, 100), Some((0, None)));
+        assert_eq!(parse_goto("+3", 10, 100), Some((13, None)));
+        assert_eq!(parse_goto("-3", 10, 100), Some((7, None)));
+        assert_eq!(parse_goto("-30", 10, 100), Some((0, None)));
+        assert_eq!(parse_goto("50%", 0, 100), Some((50, None)));
+        assert_eq!(parse_goto("250%", 0, 100), Some((100, None)));
+        // rows past the end are left for go_to to stop at the last one
+        assert_eq!(parse_goto("1000", 0, 100), Some((999, None)));
+    }
+
+    #[test]
+    fn refuses_what_is_not_a_position() {
+        for input in [
+            "", "abc", "12:", "12:x", ":5", "+", "-x", "%", "x%", "1.5", "-1%",
+        ] {
+            assert_eq!(parse_goto(input, 0, 100), None, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn splits_positions_off_file_arguments() {
+        assert_eq!(
+            split_position("no/such/file.rs:12:5"),
+            ("no/such/file.rs", Some((12, Some(5))))
+        );
+        assert_eq!(
+            split_position("no/such/file.rs:12"),
+            ("no/such/file.rs", Some((12, None)))
+        );
+        assert_eq!(
+            split_position("no/such/file.rs:12:"),
+            ("no/such/file.rs", Some((12, None)))
+        );
+        assert_eq!(split_position("no/such/file.rs"), ("no/such/file.rs", None));
+        assert_eq!(
+            split_position("no/such/file.rs:x"),
+            ("no/such/file.rs:x", None)
+        );
+        assert_eq!(split_position(":12"), (":12", None));
+        // a file that exists is opened under its whole name
+        assert_eq!(split_position("Cargo.toml"), ("Cargo.toml", None));
+    }
+
+    #[test]
+    fn go_to_stops_at_the_end_and_drops_the_selection() {
+        let mut editor_rows = EditorRows::new();
+        editor_rows.buffer.insert_row(0, "héllo");
+        editor_rows.buffer.insert_row(1, "x");
+        let mut cursor_controller = CursorController::new((80, 10));
+        cursor_controller.mark_selection(true);
+        cursor_controller.go_to(0, Some(3), &editor_rows);
+        assert_eq!(cursor_controller.position(), (3, 0));
+        assert!(cursor_controller.anchor.is_none());
+        cursor_controller.go_to(0, Some(99), &editor_rows);
+        assert_eq!(cursor_controller.position(), (6, 0));
+        cursor_controller.go_to(9, None, &editor_rows);
+        assert_eq!(cursor_controller.position(), (0, 1));
+    }
+}
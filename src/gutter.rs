@@ -0,0 +1,76 @@
+use crate::Output;
+use crossterm::queue;
+use crossterm::style::{self, Color};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    // distances from the cursor row, which keeps its own number
+    Relative,
+}
+
+impl LineNumbers {
+    fn next(self) -> Self {
+        match self {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Off,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LineNumbers::Off => "off",
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+        }
+    }
+}
+
+impl Output {
+    /* columns taken by the line numbers left of the text: the digits of the last row and
+    a space. There is no gutter when it would leave no room for the text */
+    pub(crate) fn gutter_width(&self) -> usize {
+        if self.line_numbers == LineNumbers::Off || self.editor_rows.hex.is_some() {
+            return 0;
+        }
+        let width = self.editor_rows.number_of_rows().max(1).to_string().len() + 1;
+        if width < self.win_size.0 {
+            width
+        } else {
+            0
+        }
+    }
+
+    // the number of `file_row`, or blanks for the screen lines a wrapped row continues on
+    pub(crate) fn draw_line_number(&mut self, file_row: Option<usize>, width: usize) {
+        if width == 0 {
+            return;
+        }
+        let cursor_y = self.cursor_controller.cursor_y;
+        let number = match file_row {
+            None => String::new(),
+            Some(y) if self.line_numbers == LineNumbers::Relative && y != cursor_y => {
+                y.abs_diff(cursor_y).to_string()
+            }
+            Some(y) => (y + 1).to_string(),
+        };
+        queue!(
+            self.editor_contents,
+            style::SetForegroundColor(Color::DarkGrey)
+        )
+        .unwrap();
+        self.editor_contents
+            .push_str(&format!("{:>1$} ", number, width - 1));
+        queue!(self.editor_contents, style::ResetColor).unwrap();
+    }
+
+    pub(crate) fn toggle_line_numbers(&mut self) {
+        self.line_numbers = self.line_numbers.next();
+        self.status_message
+            .set_message(format!("Line numbers {}", self.line_numbers.name()));
+    }
+}
@@ -0,0 +1,294 @@
+use crate::keymap::{Command, Key, Lookup};
+use crate::{prompt, truncate_to_width, Output, Reader};
+use crossterm::event::*;
+use crossterm::terminal::ClearType;
+use crossterm::{queue, style, terminal};
+use std::io;
+
+const BYTES_PER_ROW: usize = 16;
+// "00000000  " in front of the hex columns
+const OFFSET_WIDTH: usize = 10;
+const ASCII_COLUMN: usize = OFFSET_WIDTH + BYTES_PER_ROW * 3 + 3;
+
+/* a binary file shown as offset, hex bytes and ASCII columns. Bytes are edited in place,
+one nibble at a time, and saved back exactly as they are */
+pub struct HexView {
+    bytes: Vec<u8>,
+    // two nibbles per byte, the high one first
+    cursor: usize,
+    row_offset: usize,
+}
+
+impl HexView {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            cursor: 0,
+            row_offset: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn offset(&self) -> usize {
+        self.cursor / 2
+    }
+
+    fn number_of_rows(&self) -> usize {
+        self.bytes.len().div_ceil(BYTES_PER_ROW).max(1)
+    }
+
+    fn last_nibble(&self) -> usize {
+        (self.bytes.len() * 2).saturating_sub(1)
+    }
+
+    // screen column of the nibble `at` within its row
+    fn nibble_column(at: usize) -> usize {
+        let byte = (at / 2) % BYTES_PER_ROW;
+        OFFSET_WIDTH + byte * 3 + usize::from(byte >= BYTES_PER_ROW / 2) + at % 2
+    }
+
+    fn move_cursor(&mut self, direction: KeyCode, screen_rows: usize) {
+        let row_nibbles = BYTES_PER_ROW * 2;
+        let row_start = self.cursor - self.cursor % row_nibbles;
+        self.cursor = match direction {
+            KeyCode::Left => self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor + 1,
+            KeyCode::Up => self.cursor.checked_sub(row_nibbles).unwrap_or(self.cursor),
+            KeyCode::Down if self.cursor + row_nibbles <= self.last_nibble() => {
+                self.cursor + row_nibbles
+            }
+            KeyCode::Home => row_start,
+            KeyCode::End => row_start + row_nibbles - 1,
+            KeyCode::PageUp => self.cursor.saturating_sub(row_nibbles * screen_rows),
+            KeyCode::PageDown => self.cursor + row_nibbles * screen_rows,
+            _ => self.cursor,
+        };
+        self.cursor = self.cursor.min(self.last_nibble());
+    }
+
+    // overwrites the nibble under the cursor and steps to the next one
+    fn set_nibble(&mut self, value: u8) -> bool {
+        let byte = match self.bytes.get_mut(self.cursor / 2) {
+            None => return false,
+            Some(byte) => byte,
+        };
+        *byte = if self.cursor.is_multiple_of(2) {
+            (value << 4) | (*byte & 0x0f)
+        } else {
+            (*byte & 0xf0) | value
+        };
+        self.cursor = (self.cursor + 1).min(self.last_nibble());
+        true
+    }
+
+    // the next occurrence of `needle` after the cursor byte, wrapping around the end
+    fn find(&self, needle: &[u8]) -> Option<usize> {
+        let start = self.offset() + 1;
+        let find_from = |from: usize| {
+            self.bytes
+                .get(from..)?
+                .windows(needle.len())
+                .position(|window| window == needle)
+                .map(|at| at + from)
+        };
+        find_from(start).or_else(|| find_from(0))
+    }
+
+    fn scroll(&mut self, screen_rows: usize) {
+        let screen_rows = screen_rows.max(1);
+        let row = self.cursor / (BYTES_PER_ROW * 2);
+        self.row_offset = self.row_offset.min(row);
+        if row >= self.row_offset + screen_rows {
+            self.row_offset = row - screen_rows + 1;
+        }
+    }
+
+    fn render_row(&self, row: usize) -> Option<String> {
+        let start = row * BYTES_PER_ROW;
+        if row >= self.number_of_rows() {
+            return None;
+        }
+        let bytes = &self.bytes[start..(start + BYTES_PER_ROW).min(self.bytes.len())];
+        let mut render = format!("{:08x}  ", start);
+        for i in 0..BYTES_PER_ROW {
+            match bytes.get(i) {
+                Some(byte) => render.push_str(&format!("{:02x} ", byte)),
+                None => render.push_str("   "),
+            }
+            if i + 1 == BYTES_PER_ROW / 2 {
+                render.push(' ');
+            }
+        }
+        render.push_str(" |");
+        render.extend(bytes.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                char::from(byte)
+            } else {
+                '.'
+            }
+        }));
+        render.push('|');
+        Some(render)
+    }
+
+    pub fn write_to(&self, writer: &mut impl io::Write) -> io::Result<usize> {
+        writer.write_all(&self.bytes)?;
+        Ok(self.bytes.len())
+    }
+}
+
+// "7f 45 4c46" -> [0x7f, 0x45, 0x4c, 0x46]
+fn parse_hex(input: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = input
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .map(|ch| ch.to_digit(16).map(|digit| digit as u8))
+        .collect::<Option<_>>()?;
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect(),
+    )
+}
+
+impl Output {
+    pub(crate) fn hex_scroll(&mut self) {
+        let screen_rows = self.win_size.1;
+        if let Some(hex) = &mut self.editor_rows.hex {
+            hex.scroll(screen_rows)
+        }
+    }
+
+    // where the terminal cursor goes: on the nibble being edited
+    pub(crate) fn hex_cursor(&self) -> Option<(usize, usize)> {
+        self.editor_rows.hex.as_ref().map(|hex| {
+            (
+                HexView::nibble_column(hex.cursor),
+                hex.cursor / (BYTES_PER_ROW * 2) - hex.row_offset,
+            )
+        })
+    }
+
+    pub(crate) fn draw_hex_rows(&mut self) {
+        let hex = match &self.editor_rows.hex {
+            None => return,
+            Some(hex) => hex,
+        };
+        let cursor_byte = hex.offset() % BYTES_PER_ROW;
+        let cursor_row = hex.cursor / (BYTES_PER_ROW * 2);
+        for i in 0..self.win_size.1 {
+            let row = i + hex.row_offset;
+            match hex.render_row(row) {
+                None => self.editor_contents.push('~'),
+                Some(render) => {
+                    let render = truncate_to_width(&render, self.win_size.0);
+                    // the cursor byte is also marked in the ASCII column
+                    let mark = ASCII_COLUMN + cursor_byte;
+                    if row == cursor_row && !hex.bytes.is_empty() && mark < render.len() {
+                        self.editor_contents.push_str(&render[..mark]);
+                        self.editor_contents
+                            .push_str(&style::Attribute::Reverse.to_string());
+                        self.editor_contents.push_str(&render[mark..mark + 1]);
+                        self.editor_contents
+                            .push_str(&style::Attribute::Reset.to_string());
+                        self.editor_contents.push_str(&render[mark + 1..]);
+                    } else {
+                        self.editor_contents.push_str(render);
+                    }
+                }
+            }
+            queue!(
+                self.editor_contents,
+                terminal::Clear(ClearType::UntilNewLine)
+            )
+            .unwrap();
+            self.editor_contents.push_str("\r\n");
+        }
+    }
+
+    /* handles a key while the buffer is in hex view. Returns false for the keys that work
+    the same in every buffer, like saving or switching buffers, everything else is taken */
+    pub(crate) fn hex_keypress(&mut self, key_event: KeyEvent) -> crossterm::Result<bool> {
+        if self.editor_rows.hex.is_none() {
+            return Ok(false);
+        }
+        match self.keymap.lookup(&[Key::from(key_event)]) {
+            Lookup::Command(command) if command.in_any_buffer() => return Ok(false),
+            // the chord might end in one of those
+            Lookup::Prefix => return Ok(false),
+            Lookup::Command(Command::Find) => {
+                self.find_bytes()?;
+                return Ok(true);
+            }
+            _ => {}
+        }
+        let screen_rows = self.win_size.1;
+        let hex = match &mut self.editor_rows.hex {
+            None => return Ok(false),
+            Some(hex) => hex,
+        };
+        match key_event {
+            KeyEvent {
+                code:
+                    direction @ (KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Home
+                    | KeyCode::End
+                    | KeyCode::PageUp
+                    | KeyCode::PageDown),
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => hex.move_cursor(direction, screen_rows),
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => {
+                if let Some(value) = ch.to_digit(16) {
+                    if hex.set_nibble(value as u8) {
+                        self.history.forget_saved();
+                        self.dirty += 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn find_bytes(&mut self) -> crossterm::Result<()> {
+        let input = match prompt!(
+            self,
+            "Find bytes: {} (hex, e.g. 7f 45 4c 46, ESC to cancel)"
+        ) {
+            None => return Ok(()),
+            Some(input) => input,
+        };
+        let needle = match parse_hex(&input) {
+            None => {
+                self.status_message
+                    .set_message(format!("Not a byte sequence: \"{}\"", input));
+                return Ok(());
+            }
+            Some(needle) => needle,
+        };
+        if let Some(hex) = &mut self.editor_rows.hex {
+            match hex.find(&needle) {
+                None => self
+                    .status_message
+                    .set_message(format!("\"{}\" not found", input)),
+                Some(at) => hex.cursor = at * 2,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+/* undo / redo log for every edit made through Output */
+
+#[derive(Clone)]
+pub enum EditOp {
+    Insert { x: usize, y: usize, text: String },
+    Delete { x: usize, y: usize, text: String },
+    InsertRow { y: usize },
+    RemoveRow { y: usize },
+}
+
+impl EditOp {
+    fn inverse(&self) -> EditOp {
+        match self.clone() {
+            EditOp::Insert { x, y, text } => EditOp::Delete { x, y, text },
+            EditOp::Delete { x, y, text } => EditOp::Insert { x, y, text },
+            EditOp::InsertRow { y } => EditOp::RemoveRow { y },
+            EditOp::RemoveRow { y } => EditOp::InsertRow { y },
+        }
+    }
+
+    // folds `next` into `self` when both are part of the same run of typing or deleting
+    fn merge(&mut self, next: &EditOp) -> bool {
+        match (self, next) {
+            (
+                EditOp::Insert { x, y, text },
+                EditOp::Insert {
+                    x: next_x,
+                    y: next_y,
+                    text: next_text,
+                },
+            ) if y == next_y && *x + text.len() == *next_x && !next_text.contains('\n') => {
+                text.push_str(next_text);
+                true
+            }
+            (
+                EditOp::Delete { x, y, text },
+                EditOp::Delete {
+                    x: next_x,
+                    y: next_y,
+                    text: next_text,
+                },
+            ) if y == next_y && *next_x + next_text.len() == *x && !next_text.contains('\n') => {
+                text.insert_str(0, next_text);
+                *x = *next_x;
+                true
+            }
+            // Delete takes text away after the cursor, which stays where it is
+            (
+                EditOp::Delete { x, y, text },
+                EditOp::Delete {
+                    x: next_x,
+                    y: next_y,
+                    text: next_text,
+                },
+            ) if y == next_y && x == next_x && !next_text.contains('\n') => {
+                text.push_str(next_text);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct Change {
+    ops: Vec<EditOp>,
+    cursor_before: (usize, usize),
+    cursor_after: (usize, usize),
+}
+
+impl Change {
+    pub fn cursor_before(&self) -> (usize, usize) {
+        self.cursor_before
+    }
+
+    pub fn cursor_after(&self) -> (usize, usize) {
+        self.cursor_after
+    }
+
+    pub fn ops(&self) -> impl Iterator<Item = &EditOp> {
+        self.ops.iter()
+    }
+
+    // the operations that take the buffer back to how it was before this change
+    pub fn inverse_ops(&self) -> impl Iterator<Item = EditOp> + '_ {
+        self.ops.iter().rev().map(EditOp::inverse)
+    }
+}
+
+pub struct History {
+    undo_stack: Vec<Change>,
+    redo_stack: Vec<Change>,
+    saved_at: Option<usize>,
+    sealed: bool,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            saved_at: Some(0),
+            sealed: true,
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        op: EditOp,
+        cursor_before: (usize, usize),
+        cursor_after: (usize, usize),
+    ) {
+        self.clear_redo();
+        if !self.sealed {
+            if let Some(change) = self.undo_stack.last_mut() {
+                if change.cursor_after == cursor_before {
+                    let merged = change.ops.last_mut().is_some_and(|last| last.merge(&op));
+                    // typing on the empty line past the end first creates the row
+                    let follows_new_row = matches!(
+                        (change.ops.as_slice(), &op),
+                        ([EditOp::InsertRow { y }], EditOp::Insert { y: at, .. }) if y == at
+                    );
+                    if merged || follows_new_row {
+                        if follows_new_row {
+                            change.ops.push(op);
+                        }
+                        change.cursor_after = cursor_after;
+                        return;
+                    }
+                }
+            }
+        }
+        self.undo_stack.push(Change {
+            ops: vec![op],
+            cursor_before,
+            cursor_after,
+        });
+        self.sealed = false;
+    }
+
+    // records several operations, e.g. a replace-all, as a single undo step
+    pub fn record_all(
+        &mut self,
+        ops: Vec<EditOp>,
+        cursor_before: (usize, usize),
+        cursor_after: (usize, usize),
+    ) {
+        self.clear_redo();
+        self.undo_stack.push(Change {
+            ops,
+            cursor_before,
+            cursor_after,
+        });
+        self.sealed = true;
+    }
+
+    fn clear_redo(&mut self) {
+        if !self.redo_stack.is_empty() {
+            self.redo_stack.clear();
+            if self.saved_at.is_some_and(|at| at > self.undo_stack.len()) {
+                self.saved_at = None;
+            }
+        }
+    }
+
+    // ends the current group so the next edit starts a new undo step
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    pub fn undo(&mut self) -> Option<&Change> {
+        self.sealed = true;
+        let change = self.undo_stack.pop()?;
+        self.redo_stack.push(change);
+        self.redo_stack.last()
+    }
+
+    pub fn redo(&mut self) -> Option<&Change> {
+        self.sealed = true;
+        let change = self.redo_stack.pop()?;
+        self.undo_stack.push(change);
+        self.undo_stack.last()
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.sealed = true;
+        self.saved_at = Some(self.undo_stack.len());
+    }
+
+    // for changes made outside the undo stack, the saved state can't be reached again
+    pub fn forget_saved(&mut self) {
+        self.sealed = true;
+        self.saved_at = None;
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved_at == Some(self.undo_stack.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(history: &mut History, x: usize, text: &str) {
+        let op = EditOp::Insert {
+            x,
+            y: 0,
+            text: text.into(),
+        };
+        history.record(op, (x, 0), (x + text.len(), 0));
+    }
+
+    fn inserted(change: &Change) -> Vec<String> {
+        change
+            .ops()
+            .map(|op| match op {
+                EditOp::Insert { text, .. } => text.clone(),
+                _ => panic!("not an insert"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn typing_merges_into_one_step() {
+        let mut history = History::new();
+        insert(&mut history, 0, "a");
+        insert(&mut history, 1, "b");
+        insert(&mut history, 2, "c");
+        // a newline ends the run
+        insert(&mut history, 3, "\n");
+        let change = history.undo().unwrap();
+        assert_eq!(inserted(change), ["\n"]);
+        let change = history.undo().unwrap();
+        assert_eq!(inserted(change), ["abc"]);
+        assert_eq!(change.cursor_before(), (0, 0));
+        assert_eq!(change.cursor_after(), (3, 0));
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn sealing_starts_a_new_step() {
+        let mut history = History::new();
+        insert(&mut history, 0, "a");
+        history.seal();
+        insert(&mut history, 1, "b");
+        assert_eq!(inserted(history.undo().unwrap()), ["b"]);
+        assert_eq!(inserted(history.undo().unwrap()), ["a"]);
+    }
+
+    #[test]
+    fn undoing_back_to_the_save_is_clean() {
+        let mut history = History::new();
+        assert!(history.is_saved());
+        insert(&mut history, 0, "a");
+        history.mark_saved();
+        insert(&mut history, 1, "b");
+        assert!(!history.is_saved());
+        history.undo();
+        assert!(history.is_saved());
+        history.undo();
+        assert!(!history.is_saved());
+        history.redo();
+        assert!(history.is_saved());
+    }
+
+    #[test]
+    fn dropping_the_redo_stack_forgets_a_later_save() {
+        let mut history = History::new();
+        insert(&mut history, 0, "a");
+        history.mark_saved();
+        history.undo();
+        assert!(!history.is_saved());
+        // the saved state was on the redo stack, which this edit throws away
+        insert(&mut history, 0, "b");
+        assert!(history.redo().is_none());
+        assert!(!history.is_saved());
+        history.undo();
+        assert!(!history.is_saved());
+    }
+}
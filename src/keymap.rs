@@ -0,0 +1,553 @@
+use crate::config;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::{fs, io};
+
+/* what the keys do. `keys.toml` in the config directory binds keys to command names on
+top of the defaults below, keys separated by spaces being a chord pressed one after the
+other, and "none" takes a binding away:
+
+    "Ctrl-K Ctrl-S" = "save"
+    "Ctrl-S" = "none"
+*/
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Command {
+    Quit,
+    Save,
+    Find,
+    Replace,
+    Undo,
+    Redo,
+    Open,
+    GoTo,
+    ToggleLineEnding,
+    Cut,
+    Copy,
+    Paste,
+    PasteFromRing,
+    ToggleWrap,
+    ToggleLineNumbers,
+    ToggleVim,
+    NextBuffer,
+    PreviousBuffer,
+    ListBuffers,
+    // reads the settings and key bindings again
+    ReloadSettings,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    MoveHome,
+    MoveEnd,
+    PageUp,
+    PageDown,
+    SelectLeft,
+    SelectRight,
+    SelectUp,
+    SelectDown,
+    SelectHome,
+    SelectEnd,
+    SelectPageUp,
+    SelectPageDown,
+    DeleteBack,
+    DeleteForward,
+    // also accepts what is typed into a prompt
+    Newline,
+    // indents the selected rows when there is a selection
+    InsertTab,
+    Outdent,
+    // drops the selection, or leaves a prompt
+    Cancel,
+}
+
+// the names keys.toml uses
+const COMMANDS: &[(&str, Command)] = &[
+    ("quit", Command::Quit),
+    ("save", Command::Save),
+    ("find", Command::Find),
+    ("replace", Command::Replace),
+    ("undo", Command::Undo),
+    ("redo", Command::Redo),
+    ("open", Command::Open),
+    ("go-to", Command::GoTo),
+    ("toggle-line-ending", Command::ToggleLineEnding),
+    ("cut", Command::Cut),
+    ("copy", Command::Copy),
+    ("paste", Command::Paste),
+    ("paste-from-ring", Command::PasteFromRing),
+    ("toggle-wrap", Command::ToggleWrap),
+    ("toggle-line-numbers", Command::ToggleLineNumbers),
+    ("toggle-vim", Command::ToggleVim),
+    ("next-buffer", Command::NextBuffer),
+    ("previous-buffer", Command::PreviousBuffer),
+    ("list-buffers", Command::ListBuffers),
+    ("reload-settings", Command::ReloadSettings),
+    ("move-left", Command::MoveLeft),
+    ("move-right", Command::MoveRight),
+    ("move-up", Command::MoveUp),
+    ("move-down", Command::MoveDown),
+    ("move-home", Command::MoveHome),
+    ("move-end", Command::MoveEnd),
+    ("page-up", Command::PageUp),
+    ("page-down", Command::PageDown),
+    ("select-left", Command::SelectLeft),
+    ("select-right", Command::SelectRight),
+    ("select-up", Command::SelectUp),
+    ("select-down", Command::SelectDown),
+    ("select-home", Command::SelectHome),
+    ("select-end", Command::SelectEnd),
+    ("select-page-up", Command::SelectPageUp),
+    ("select-page-down", Command::SelectPageDown),
+    ("delete-back", Command::DeleteBack),
+    ("delete-forward", Command::DeleteForward),
+    ("newline", Command::Newline),
+    ("insert-tab", Command::InsertTab),
+    ("outdent", Command::Outdent),
+    ("cancel", Command::Cancel),
+];
+
+const DEFAULT_KEYS: &[(&str, Command)] = &[
+    ("Ctrl-Q", Command::Quit),
+    ("Ctrl-S", Command::Save),
+    ("Ctrl-F", Command::Find),
+    ("Ctrl-R", Command::Replace),
+    ("Ctrl-Z", Command::Undo),
+    ("Ctrl-Y", Command::Redo),
+    ("Ctrl-O", Command::Open),
+    ("Ctrl-G", Command::GoTo),
+    ("Ctrl-E", Command::ToggleLineEnding),
+    ("Ctrl-X", Command::Cut),
+    ("Ctrl-C", Command::Copy),
+    ("Ctrl-V", Command::Paste),
+    ("Alt-V", Command::PasteFromRing),
+    ("Alt-Z", Command::ToggleWrap),
+    ("Alt-L", Command::ToggleLineNumbers),
+    ("Alt-M", Command::ToggleVim),
+    ("Ctrl-N", Command::NextBuffer),
+    ("Ctrl-P", Command::PreviousBuffer),
+    ("Ctrl-B", Command::ListBuffers),
+    ("F5", Command::ReloadSettings),
+    ("Left", Command::MoveLeft),
+    ("Right", Command::MoveRight),
+    ("Up", Command::MoveUp),
+    ("Down", Command::MoveDown),
+    ("Home", Command::MoveHome),
+    ("End", Command::MoveEnd),
+    ("PageUp", Command::PageUp),
+    ("PageDown", Command::PageDown),
+    ("Shift-Left", Command::SelectLeft),
+    ("Shift-Right", Command::SelectRight),
+    ("Shift-Up", Command::SelectUp),
+    ("Shift-Down", Command::SelectDown),
+    ("Shift-Home", Command::SelectHome),
+    ("Shift-End", Command::SelectEnd),
+    ("Shift-PageUp", Command::SelectPageUp),
+    ("Shift-PageDown", Command::SelectPageDown),
+    ("Backspace", Command::DeleteBack),
+    ("Delete", Command::DeleteForward),
+    ("Enter", Command::Newline),
+    ("Tab", Command::InsertTab),
+    ("BackTab", Command::Outdent),
+    ("Esc", Command::Cancel),
+];
+
+// what the help line lists, commands that share a label are shown together
+const HELP: &[(&[Command], &str)] = &[
+    (&[Command::Save], "Save"),
+    (&[Command::Quit], "Quit"),
+    (&[Command::Find], "Find"),
+    (&[Command::Replace], "Replace"),
+    (&[Command::Undo, Command::Redo], "Undo/Redo"),
+    (
+        &[Command::Cut, Command::Copy, Command::Paste],
+        "Cut/Copy/Paste",
+    ),
+    (&[Command::Open], "Open"),
+    (&[Command::GoTo], "Go to"),
+    (&[Command::ToggleLineEnding], "LF/CRLF"),
+    (&[Command::ToggleWrap], "Wrap"),
+    (&[Command::ToggleLineNumbers], "Line numbers"),
+    (&[Command::ToggleVim], "Vim mode"),
+    (
+        &[
+            Command::NextBuffer,
+            Command::PreviousBuffer,
+            Command::ListBuffers,
+        ],
+        "Next/Previous/List buffers",
+    ),
+];
+
+impl Command {
+    fn parse(name: &str) -> Option<Self> {
+        COMMANDS
+            .iter()
+            .find(|(command_name, _)| *command_name == name)
+            .map(|(_, command)| *command)
+    }
+
+    // commands that also work in the hex view
+    pub fn in_any_buffer(self) -> bool {
+        matches!(
+            self,
+            Command::Quit
+                | Command::Save
+                | Command::Open
+                | Command::NextBuffer
+                | Command::PreviousBuffer
+                | Command::ListBuffers
+                | Command::ReloadSettings
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    // Shift is already part of a typed character, and of BackTab
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let code = match code {
+            KeyCode::Char(ch) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(ch.to_ascii_uppercase())
+            }
+            code => code,
+        };
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Self { code, modifiers }
+    }
+
+    // "Ctrl-K", "Alt-Shift-V", "PageUp", "F5" or a character
+    fn parse(text: &str) -> Option<Self> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        loop {
+            let (modifier, after) = match rest.split_once('-') {
+                Some((modifier, after)) if !after.is_empty() => (modifier, after),
+                _ => break,
+            };
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return None,
+            };
+            rest = after;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next()?, chars.next()) {
+            // "Ctrl-K" is written with a capital but typed without Shift
+            (ch, None) if modifiers.is_empty() => KeyCode::Char(ch),
+            (ch, None) => KeyCode::Char(ch.to_ascii_lowercase()),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "enter" => KeyCode::Enter,
+                "esc" | "escape" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "space" => KeyCode::Char(' '),
+                name => KeyCode::F(name.strip_prefix('f')?.parse().ok()?),
+            },
+        };
+        Some(Self::new(code, modifiers))
+    }
+
+    fn name(&self) -> String {
+        let mut name = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            name.push_str("Ctrl-");
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            name.push_str("Alt-");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            name.push_str("Shift-");
+        }
+        match self.code {
+            KeyCode::Char(' ') => name.push_str("Space"),
+            KeyCode::Char(ch) if self.modifiers.is_empty() => name.push(ch),
+            KeyCode::Char(ch) if ch.is_ascii_uppercase() => {
+                name.push_str("Shift-");
+                name.push(ch)
+            }
+            KeyCode::Char(ch) => name.push(ch.to_ascii_uppercase()),
+            KeyCode::F(number) => name.push_str(&format!("F{}", number)),
+            code => name.push_str(&format!("{:?}", code)),
+        }
+        name
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(key_event: KeyEvent) -> Self {
+        Self::new(key_event.code, key_event.modifiers)
+    }
+}
+
+fn parse_chord(text: &str) -> Option<Vec<Key>> {
+    let chord: Option<Vec<Key>> = text.split_whitespace().map(Key::parse).collect();
+    chord.filter(|chord| !chord.is_empty())
+}
+
+pub fn chord_name(chord: &[Key]) -> String {
+    chord.iter().map(Key::name).collect::<Vec<_>>().join(" ")
+}
+
+// pressing one chord would get in the way of the other
+fn overlaps(chord: &[Key], other: &[Key]) -> bool {
+    chord.starts_with(other) || other.starts_with(chord)
+}
+
+pub enum Lookup {
+    Command(Command),
+    // the start of a chord, more keys are needed
+    Prefix,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, Command>,
+}
+
+impl Keymap {
+    fn defaults() -> Self {
+        Self {
+            bindings: DEFAULT_KEYS
+                .iter()
+                .map(|(keys, command)| (parse_chord(keys).expect("a valid key"), *command))
+                .collect(),
+        }
+    }
+
+    // the default keys with keys.toml on top, and what was wrong with the file
+    pub fn load() -> (Self, Vec<String>) {
+        let mut keymap = Self::defaults();
+        let mut errors = Vec::new();
+        if let Some(path) = config::config_dir().map(|dir| dir.join("keys.toml")) {
+            let name = path.display().to_string();
+            match fs::read_to_string(&path) {
+                Ok(contents) => keymap.add(&name, &contents, &mut errors),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => errors.push(format!("{}: {}", name, err)),
+            }
+        }
+        (keymap, errors)
+    }
+
+    /* binds the keys of a keys.toml. A default binding that overlaps one in the file gives
+    way to it, two in the file that overlap are a conflict and the second is left out */
+    fn add(&mut self, name: &str, contents: &str, errors: &mut Vec<String>) {
+        let table: toml::Table = match toml::from_str(contents) {
+            Ok(table) => table,
+            Err(err) => {
+                errors.push(format!("{}: {}", name, err.message()));
+                return;
+            }
+        };
+        let mut bound: Vec<Vec<Key>> = Vec::new();
+        for (keys, value) in table {
+            let chord = match parse_chord(&keys) {
+                None => {
+                    errors.push(format!("{}: \"{}\" is not a key", name, keys));
+                    continue;
+                }
+                Some(chord) => chord,
+            };
+            let command = match value.as_str() {
+                Some("none") => None,
+                Some(command_name) => match Command::parse(command_name) {
+                    None => {
+                        errors.push(format!(
+                            "{}: unknown command \"{}\" for {}",
+                            name, command_name, keys
+                        ));
+                        continue;
+                    }
+                    command => command,
+                },
+                None => {
+                    errors.push(format!("{}: {} is not bound to a command name", name, keys));
+                    continue;
+                }
+            };
+            if let Some(other) = bound.iter().find(|other| overlaps(&chord, other)) {
+                errors.push(format!(
+                    "{}: {} conflicts with {}",
+                    name,
+                    chord_name(&chord),
+                    chord_name(other)
+                ));
+                continue;
+            }
+            self.bindings.retain(|other, _| !overlaps(&chord, other));
+            if let Some(command) = command {
+                self.bindings.insert(chord.clone(), command);
+            }
+            bound.push(chord);
+        }
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        match self.bindings.get(keys) {
+            Some(command) => Lookup::Command(*command),
+            None if self.bindings.keys().any(|chord| chord.starts_with(keys)) => Lookup::Prefix,
+            None => Lookup::Unbound,
+        }
+    }
+
+    // the command of a single key, the only kind prompts know about
+    pub fn command(&self, key_event: KeyEvent) -> Option<Command> {
+        self.bindings.get(&vec![Key::from(key_event)]).copied()
+    }
+
+    // the shortest chord bound to `command`
+    pub fn keys_for(&self, command: Command) -> Option<String> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| **bound == command)
+            .map(|(chord, _)| chord_name(chord))
+            .min_by_key(|name| (name.len(), name.clone()))
+    }
+
+    pub fn help(&self) -> String {
+        let entries: Vec<String> = HELP
+            .iter()
+            .filter_map(|(commands, label)| {
+                let keys: Option<Vec<String>> = commands
+                    .iter()
+                    .map(|command| self.keys_for(*command))
+                    .collect();
+                Some(format!("{} = {}", keys?.join("/"), label))
+            })
+            .collect();
+        format!("HELP: {}", entries.join(" | "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        Key::new(code, modifiers)
+    }
+
+    fn chord(text: &str) -> Vec<Key> {
+        parse_chord(text).unwrap()
+    }
+
+    // the defaults with `contents` as keys.toml
+    fn with_keys(contents: &str) -> (Keymap, Vec<String>) {
+        let mut keymap = Keymap::defaults();
+        let mut errors = Vec::new();
+        keymap.add("keys.toml", contents, &mut errors);
+        (keymap, errors)
+    }
+
+    #[test]
+    fn parses_keys() {
+        let ctrl = KeyModifiers::CONTROL;
+        let none = KeyModifiers::NONE;
+        assert!(Key::parse("Ctrl-K") == Some(key(KeyCode::Char('k'), ctrl)));
+        assert!(Key::parse("ctrl-k") == Key::parse("Ctrl-K"));
+        assert!(Key::parse("a") == Some(key(KeyCode::Char('a'), none)));
+        assert!(Key::parse("A") == Some(key(KeyCode::Char('A'), none)));
+        assert!(Key::parse("-") == Some(key(KeyCode::Char('-'), none)));
+        assert!(Key::parse("Ctrl--") == Some(key(KeyCode::Char('-'), ctrl)));
+        assert!(Key::parse("F5") == Some(key(KeyCode::F(5), none)));
+        assert!(Key::parse("Shift-PageUp") == Some(key(KeyCode::PageUp, KeyModifiers::SHIFT)));
+        assert!(Key::parse("Space") == Some(key(KeyCode::Char(' '), none)));
+        // what the terminal sends for Alt-Shift-V
+        let alt_shift = KeyModifiers::ALT | KeyModifiers::SHIFT;
+        assert!(
+            Key::parse("Alt-Shift-V")
+                == Some(Key::from(KeyEvent::new(KeyCode::Char('V'), alt_shift)))
+        );
+        for text in ["", "Hyper-K", "Ctrl-", "Fx", "F1x", "PgUp"] {
+            assert!(Key::parse(text).is_none(), "{}", text);
+        }
+        assert!(parse_chord(" ").is_none());
+        assert!(parse_chord("Ctrl-K Nope").is_none());
+        assert_eq!(
+            chord_name(&chord("ctrl-k  shift-a alt-space F12")),
+            "Ctrl-K A Alt-Space F12"
+        );
+    }
+
+    #[test]
+    fn chords_are_looked_up_key_by_key() {
+        let (keymap, errors) = with_keys("\"Ctrl-K Ctrl-S\" = \"save\"\n\"Ctrl-S X\" = \"quit\"");
+        assert!(errors.is_empty());
+        assert!(matches!(keymap.lookup(&chord("Ctrl-K")), Lookup::Prefix));
+        assert!(matches!(
+            keymap.lookup(&chord("Ctrl-K Ctrl-S")),
+            Lookup::Command(Command::Save)
+        ));
+        assert!(matches!(
+            keymap.lookup(&chord("Ctrl-K Ctrl-Q")),
+            Lookup::Unbound
+        ));
+        // the default Ctrl-S would keep Ctrl-S X from ever being reached
+        assert!(matches!(keymap.lookup(&chord("Ctrl-S")), Lookup::Prefix));
+        let ctrl_s = KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL);
+        assert!(keymap.command(ctrl_s).is_none());
+        assert_eq!(keymap.keys_for(Command::Save).unwrap(), "Ctrl-K Ctrl-S");
+        assert!(overlaps(&chord("Ctrl-K"), &chord("Ctrl-K Ctrl-S")));
+        assert!(overlaps(&chord("Ctrl-K Ctrl-S"), &chord("Ctrl-K")));
+        assert!(!overlaps(&chord("Ctrl-K Ctrl-S"), &chord("Ctrl-K Ctrl-Q")));
+    }
+
+    #[test]
+    fn reports_what_is_wrong_in_file_order() {
+        let (keymap, errors) = with_keys(
+            r#"
+            "Ctrl-K Ctrl-K" = "quit"
+            "Ctrl-K" = "save"
+            "Hyper-X" = "save"
+            "Ctrl-J" = "jump"
+            "Ctrl-L" = 1
+            "Ctrl-Q" = "none"
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "keys.toml: Ctrl-K conflicts with Ctrl-K Ctrl-K",
+                "keys.toml: \"Hyper-X\" is not a key",
+                "keys.toml: unknown command \"jump\" for Ctrl-J",
+                "keys.toml: Ctrl-L is not bound to a command name",
+            ]
+        );
+        // of the two that conflict, the one further up is kept
+        assert!(matches!(
+            keymap.lookup(&chord("Ctrl-K Ctrl-K")),
+            Lookup::Command(Command::Quit)
+        ));
+        assert!(matches!(keymap.lookup(&chord("Ctrl-Q")), Lookup::Unbound));
+
+        let (keymap, errors) = with_keys("\"Ctrl-K\" = ");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("keys.toml: "));
+        assert!(matches!(
+            keymap.lookup(&chord("Ctrl-S")),
+            Lookup::Command(Command::Save)
+        ));
+    }
+}
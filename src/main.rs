@@ -1,20 +1,82 @@
+mod buffer;
+mod buffer_list;
+mod clipboard;
+mod config;
+mod goto;
+mod gutter;
+mod hex;
+mod history;
+mod keymap;
+mod mouse;
+mod open;
+mod save;
+mod search;
+mod selection;
+mod settings;
+mod syntax;
+mod vim;
+mod wrap;
+
 use crossterm::event::*;
 use crossterm::terminal::ClearType;
 use crossterm::{cursor, event, execute, queue, style, terminal};
+use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::io::{stdout, ErrorKind, Write};
+use std::collections::HashMap;
+use std::io::{stdout, Write};
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{self, AtomicUsize};
 use std::time::{Duration, Instant};
 use std::{cmp, env, fs, io};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use buffer::{LineEnding, TextBuffer};
+use buffer_list::Buffer;
+use clipboard::{Clipboard, SystemClipboard};
+use gutter::LineNumbers;
+use hex::HexView;
+use history::{EditOp, History};
+use keymap::{Command, Key, Keymap, Lookup};
+use search::SearchIndex;
+use settings::Settings;
+use syntax::{HighlightType, Syntax, SyntaxRegistry};
+use vim::Vim;
+use wrap::wrap_columns;
 
+// columns from one tab stop to the next, set from the `tab-width` setting
+static TAB_STOP: AtomicUsize = AtomicUsize::new(8);
+const MAX_CACHED_ROWS: usize = 4096;
+const SYNTAX_LOOKBACK: usize = 1000;
+
+fn tab_stop() -> usize {
+    TAB_STOP.load(atomic::Ordering::Relaxed)
+}
 
-const TAB_STOP: usize = 8;
-const QUIT_TIMES: u8 = 3;
+// columns a grapheme takes on screen: wide CJK and emoji count 2, combining marks 0
+fn grapheme_width(grapheme: &str) -> usize {
+    grapheme.width()
+}
+
+// the longest prefix of `text` that fits in `width` columns, never splitting a grapheme
+fn truncate_to_width(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (at, grapheme) in text.grapheme_indices(true) {
+        used += grapheme_width(grapheme);
+        if used > width {
+            return &text[..at];
+        }
+    }
+    text
+}
 
 struct CleanUp;
 
 impl Drop for CleanUp {
     fn drop(&mut self) {
+        execute!(stdout(), DisableBracketedPaste, DisableMouseCapture)
+            .expect("Unable to disable bracketed paste and mouse capture");
         terminal::disable_raw_mode().expect("Unable to disable raw mode");
         Output::clear_screen().expect("error");
     }
@@ -22,64 +84,106 @@ impl Drop for CleanUp {
 
 #[macro_export]
 macro_rules! prompt {
-    ($output:expr,$($args:tt)*) => {{
-        let output:&mut Output = $output;
+    (@or , $default:expr) => {
+        $default
+    };
+    (@or $value:expr, $default:expr) => {
+        $value
+    };
+    ($output:expr,$args:tt $(, label = $label:expr)? $(, callback = $callback:expr)? $(, complete = $complete:expr)? $(, allow_empty = $allow_empty:expr)?) => {{
+        let output: &mut Output = $output;
         let mut input = String::with_capacity(32);
+        // `label` is shown in front of the prompt, `callback` runs after every keystroke
+        let label = prompt!(@or $($label)?, |_: &Output| String::new());
+        let callback = prompt!(@or $($callback)?, |_: &mut Output, _: &str, _: KeyEvent| {});
+        // `complete` turns Tab into completion, returning the new input and the candidates
+        let complete: Option<fn(&Output, &str) -> (String, Vec<String>)> =
+            prompt!(@or $(Some($complete))?, None);
+        let allow_empty: bool = prompt!(@or $($allow_empty)?, false);
+        let mut cancelled = false;
+        let mut candidates: Vec<String> = Vec::new();
         loop {
-            output.status_message.set_message(format!($($args)*, input));
+            let mut message = format!("{}{}", label(&*output), format!($args, input));
+            if !candidates.is_empty() {
+                message.push_str(&format!(" | {}", candidates.join(" ")));
+            }
+            output.status_message.set_message(message);
             output.refresh_screen()?;
-            match Reader.read_key()? {
-                KeyEvent {
-                    code:KeyCode::Enter,
-                    modifiers:KeyModifiers::NONE
-                } => {
-                    if !input.is_empty() {
-                        output.status_message.set_message(String::new());
-                        break;
-                    }
+            let key_event = match Reader.read_event()? {
+                // a paste is typed into the input as a single line
+                crossterm::event::Event::Paste(text) => {
+                    input.extend(text.chars().filter(|ch| !matches!(ch, '\n' | '\r')));
+                    KeyEvent::new(KeyCode::Null, KeyModifiers::NONE)
                 }
-                KeyEvent {
-                    code: KeyCode::Esc,             // escape to avoide the saving of file
-                    ..
-                } => {
+                crossterm::event::Event::Key(key_event) => key_event,
+                crossterm::event::Event::Resize(columns, rows) => {
+                    output.resize(columns, rows)?;
+                    continue;
+                }
+                _ => continue,
+            };
+            candidates.clear();
+            match output.keymap.command(key_event) {
+                Some($crate::keymap::Command::Newline) if allow_empty || !input.is_empty() => {
+                    output.status_message.set_message(String::new());
+                    callback(output, &input, key_event);
+                    break;
+                }
+                // escape to avoide the saving of file
+                Some($crate::keymap::Command::Cancel) => {
                     output.status_message.set_message(String::new());
                     input.clear();
+                    cancelled = true;
+                    callback(output, &input, key_event);
                     break;
                 }
                 /* adding the following for the deletion of character*/
-                KeyEvent {
-                    code: KeyCode::Backspace | KeyCode::Delete,
-                    modifiers: KeyModifiers::NONE,
-                } =>  {
+                Some($crate::keymap::Command::DeleteBack | $crate::keymap::Command::DeleteForward) => {
                     input.pop();
                 }
                 /* end */
-                KeyEvent {
-                    code: code @ (KeyCode::Char(..) | KeyCode::Tab),
-                    modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
-                } => input.push(match code {
+                Some($crate::keymap::Command::InsertTab) if complete.is_some() => {
+                    if let Some(complete) = complete {
+                        (input, candidates) = complete(&*output, &input);
+                    }
+                }
+                _ => match key_event {
+                    KeyEvent {
+                        code: code @ (KeyCode::Char(..) | KeyCode::Tab),
+                        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                        ..
+                    } => input.push(match code {
                         KeyCode::Tab => '\t',
                         KeyCode::Char(ch) => ch,
                         _ => unreachable!(),
                     }),
-
-                _=> {}
+                    _ => {}
+                },
             }
+            // re-run after every keystroke, e.g. for incremental search
+            callback(output, &input, key_event);
+        }
+        if cancelled || (input.is_empty() && !allow_empty) {
+            None
+        } else {
+            Some(input)
         }
-        if input.is_empty() { None } else { Some (input) }
     }};
 }
 
 struct StatusMessage {
     message: Option<String>,
     set_time: Option<Instant>,
+    // how long a message stays, the `message-timeout` setting
+    timeout: Duration,
 }
 
 impl StatusMessage {
-    fn new(initial_message: String) -> Self {
+    fn new(initial_message: String, timeout: Duration) -> Self {
         Self {
             message: Some(initial_message),
             set_time: Some(Instant::now()),
+            timeout,
         }
     }
 
@@ -90,7 +194,7 @@ impl StatusMessage {
 
     fn message(&mut self) -> Option<&String> {
         self.set_time.and_then(|time| {
-            if time.elapsed() > Duration::from_secs(5) {
+            if time.elapsed() > self.timeout {
                 self.message = None;
                 self.set_time = None;
                 None
@@ -101,138 +205,254 @@ impl StatusMessage {
     }
 }
 
-#[derive(Default)]
+// all of `errors` on the one status line, the first error first
+fn join_errors(errors: &[String]) -> Option<String> {
+    (!errors.is_empty()).then(|| errors.join(" | "))
+}
+
+// a row as drawn on screen, rendered from the buffer the first time it is shown
 struct Row {
-    row_content: String,
     render: String,
+    highlight: Vec<HighlightType>,
+    // whether a multi-line comment is still open at the end of the row
+    ends_in_comment: bool,
 }
 
 impl Row {
-    fn new(row_content: String, render: String) -> Self {
+    fn new(row_content: &str) -> Self {
         Self {
-            row_content,
-            render,
+            render: EditorRows::render_row(row_content),
+            highlight: Vec::new(),
+            ends_in_comment: false,
         }
     }
+}
 
-    fn insert_char(&mut self, at: usize, ch: char) {
-        self.row_content.insert(at, ch);
-        EditorRows::render_row(self)
-    }
+// byte offset of the grapheme boundary before `at`
+fn previous_grapheme(row: &str, at: usize) -> usize {
+    row[..at]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(index, _)| index)
+}
+
+// byte offset of the grapheme boundary after `at`
+fn next_grapheme(row: &str, at: usize) -> usize {
+    row[at..]
+        .graphemes(true)
+        .next()
+        .map_or(at, |grapheme| at + grapheme.len())
+}
 
-    fn delete_char(&mut self, at: usize) {
-        self.row_content.remove(at);
-        EditorRows::render_row(self)
+// screen column in the rendered row that corresponds to byte `at` of `row`
+fn get_render_x(row: &str, at: usize) -> usize {
+    let tab_stop = tab_stop();
+    row[..at].graphemes(true).fold(0, |render_x, grapheme| {
+        if grapheme == "\t" {
+            render_x + (tab_stop - 1) - (render_x % tab_stop) + 1
+        } else {
+            render_x + grapheme_width(grapheme)
+        }
+    })
+}
+
+// byte offset of the grapheme covering screen column `render_x`, the row length past the end
+fn get_cursor_x(row: &str, render_x: usize) -> usize {
+    let tab_stop = tab_stop();
+    let mut current = 0;
+    for (at, grapheme) in row.grapheme_indices(true) {
+        current = if grapheme == "\t" {
+            current + (tab_stop - 1) - (current % tab_stop) + 1
+        } else {
+            current + grapheme_width(grapheme)
+        };
+        if current > render_x {
+            return at;
+        }
     }
+    row.len()
 }
 
 struct EditorRows {
-    row_contents: Vec<Row>,
+    buffer: TextBuffer,
+    // rendered and highlighted rows by index, filled in lazily as they come into view
+    cache: HashMap<usize, Row>,
     filename: Option<PathBuf>,
+    syntax: Option<Rc<Syntax>>,
+    // binary files are shown and edited as bytes instead, `buffer` stays empty
+    hex: Option<HexView>,
 }
 
 impl EditorRows {
     fn new() -> Self {
-        match env::args().nth(1) {
-            None => Self {
-                row_contents: Vec::new(),
-                filename: None,
-            },
-            Some(file) => Self::from_file(file.into()),
+        Self {
+            buffer: TextBuffer::new(),
+            cache: HashMap::new(),
+            filename: None,
+            syntax: None,
+            hex: None,
         }
     }
 
-    fn from_file(file: PathBuf) -> Self {
-        let file_contents = fs::read_to_string(&file).expect("Unable to read file"); //modify
-        Self {
+    // a path that does not exist yet opens as an empty buffer, the file is created on save
+    fn from_file(file: PathBuf, syntax_registry: &SyntaxRegistry) -> io::Result<Self> {
+        let (buffer, hex) = match fs::File::open(&file).and_then(TextBuffer::from_reader) {
+            Ok(buffer) => (buffer, None),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (TextBuffer::new(), None),
+            Err(err) if buffer::is_binary_file(&err) => {
+                (TextBuffer::new(), Some(HexView::new(fs::read(&file)?)))
+            }
+            Err(err) => return Err(err),
+        };
+        let mut editor_rows = Self {
+            buffer,
+            cache: HashMap::new(),
             filename: Some(file),
-            row_contents: file_contents
-                .lines()
-                .map(|it| {
-                    let mut row = Row::new(it.into(), String::new());
-                    Self::render_row(&mut row);
-                    row
-                })
-                .collect(),
-        }
+            syntax: None,
+            hex,
+        };
+        editor_rows.select_syntax(syntax_registry);
+        Ok(editor_rows)
+    }
+
+    // picks the highlighting rules from the file extension, rows are re-highlighted when drawn
+    fn select_syntax(&mut self, syntax_registry: &SyntaxRegistry) {
+        let extension = self
+            .filename
+            .as_ref()
+            .and_then(|path| path.extension())
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        self.syntax = syntax_registry.find(extension);
+        self.cache.clear();
+    }
+
+    fn file_name(&self) -> &str {
+        self.filename
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or("[Unknown file]")
     }
 
     // customizing rows
     fn number_of_rows(&self) -> usize {
-        self.row_contents.len()
+        self.buffer.number_of_rows()
     }
 
-    fn get_row(&self, at: usize) -> &str {
-        &self.row_contents[at].row_content
+    fn get_row(&self, at: usize) -> Cow<'_, str> {
+        self.buffer.row(at)
     }
 
-    fn get_render(&self, at: usize) -> &String {
-        &self.row_contents[at].render
+    /* the rendered row `at`. Highlighting needs to know whether the rows above left a
+    multi-line comment open, so uncached rows above it are rendered first, looking back
+    at most SYNTAX_LOOKBACK rows */
+    fn get_editor_row(&mut self, at: usize) -> &Row {
+        if !self.cache.contains_key(&at) {
+            let first = match self.syntax {
+                None => at,
+                Some(_) => {
+                    let lookback = at.saturating_sub(SYNTAX_LOOKBACK);
+                    (lookback..at)
+                        .rev()
+                        .find(|it| self.cache.contains_key(it))
+                        .map_or(lookback, |it| it + 1)
+                }
+            };
+            (first..=at).for_each(|it| self.cache_row(it));
+        }
+        &self.cache[&at]
     }
 
-    fn get_editor_row(&self, at: usize) -> &Row {
-        &self.row_contents[at]
+    fn cache_row(&mut self, at: usize) {
+        let mut row = Row::new(&self.buffer.row(at));
+        if let Some(syntax) = &self.syntax {
+            let in_comment = at > 0
+                && self
+                    .cache
+                    .get(&(at - 1))
+                    .is_some_and(|row| row.ends_in_comment);
+            syntax.highlight(&mut row, in_comment);
+        }
+        self.cache.insert(at, row);
+    }
+
+    // forgets rendered rows far away from `around` once the cache grows too big
+    fn trim_cache(&mut self, around: usize) {
+        if self.cache.len() > MAX_CACHED_ROWS {
+            self.cache
+                .retain(|at, _| at.abs_diff(around) < MAX_CACHED_ROWS / 2);
+        }
     }
 
-    fn get_editor_row_mut(&mut self, at: usize) -> &mut Row {
-        &mut self.row_contents[at]
+    // byte offset in row `at` for screen column `render_x`, 0 on the line past the end
+    fn column_to_x(&self, at: usize, render_x: usize) -> usize {
+        if at < self.number_of_rows() {
+            get_cursor_x(&self.get_row(at), render_x)
+        } else {
+            0
+        }
     }
 
-    fn render_row(row: &mut Row) {
+    fn render_row(row_content: &str) -> String {
+        let tab_stop = tab_stop();
         let mut index = 0;
-        let capacity = row
-            .row_content
+        let capacity = row_content
             .chars()
-            .fold(0, |acc, next| acc + if next == '\t' { TAB_STOP } else { 1 });
-        row.render = String::with_capacity(capacity);
-        row.row_content.chars().for_each(|c| {
-            index += 1;
-            if c == '\t' {
-                row.render.push(' ');
-                while index % TAB_STOP != 0 {
-                    row.render.push(' ');
+            .fold(0, |acc, next| acc + if next == '\t' { tab_stop } else { 1 });
+        let mut render = String::with_capacity(capacity);
+        row_content.graphemes(true).for_each(|grapheme| {
+            if grapheme == "\t" {
+                index += 1;
+                render.push(' ');
+                while index % tab_stop != 0 {
+                    render.push(' ');
                     index += 1
                 }
             } else {
-                row.render.push(c);
+                index += grapheme_width(grapheme);
+                render.push_str(grapheme);
             }
         });
-    }
-
-    // inserting the rows
-    fn insert_row(&mut self, at: usize, contents: String) {
-        let mut new_row = Row::new(contents, String::new());
-        EditorRows::render_row(&mut new_row);
-        self.row_contents.insert(at, new_row);
+        render
     }
 
     // save the file
-    fn save(&mut self) -> io::Result<usize> {
+    fn save(&mut self, backup: bool) -> io::Result<usize> {
         match &self.filename {
-            None => Err(io::Error::new(ErrorKind::Other, "no file name specified")),
-            Some(name) => {
-                let mut file = fs::OpenOptions::new().write(true).create(true).open(name)?;
-                let contents: String = self
-                    .row_contents
-                    .iter()
-                    .map(|it| it.row_content.as_str())
-                    .collect::<Vec<&str>>()
-                    .join("\n");
-                file.set_len(contents.len() as u64)?;
-                file.write_all(contents.as_bytes())?;
-                Ok(contents.as_bytes().len())
-            }
+            None => Err(io::Error::other("no file name specified")),
+            Some(name) => match &self.hex {
+                Some(hex) => save::save_atomically(name, backup, |writer| hex.write_to(writer)),
+                None => save::save_atomically(name, backup, |writer| self.buffer.write_to(writer)),
+            },
         }
     }
 
-    fn join_adjacent_rows(&mut self, at: usize) {
-        let current_row = self.row_contents.remove(at);
-        let previous_row = self.get_editor_row_mut(at - 1);
-        previous_row.row_content.push_str(&current_row.row_content);
-        Self::render_row(previous_row);
+    // every change to the text goes through here, rendered rows from the edit on are stale
+    fn apply(&mut self, op: &EditOp) {
+        let y = match op {
+            EditOp::Insert { x, y, text } => {
+                self.buffer.insert(*x, *y, text);
+                *y
+            }
+            EditOp::Delete { x, y, text } => {
+                self.buffer.remove(*x, *y, text);
+                *y
+            }
+            EditOp::InsertRow { y } => {
+                self.buffer.insert_row(*y, "");
+                *y
+            }
+            EditOp::RemoveRow { y } => {
+                self.buffer.remove_row(*y);
+                *y
+            }
+        };
+        self.cache.retain(|at, _| *at < y);
     }
 }
 
+#[derive(Clone, Copy)]
 struct CursorController {
     cursor_x: usize,
     cursor_y: usize,
@@ -241,6 +461,13 @@ struct CursorController {
     row_offset: usize,
     column_offset: usize,
     render_x: usize,
+    soft_wrap: bool,
+    // with soft wrap on, the screen line of row `row_offset` shown at the top
+    line_offset: usize,
+    // the other end of the selection, the cursor being one end
+    anchor: Option<(usize, usize)>,
+    // the mouse wheel scrolled the view away, it doesn't follow the cursor until a key is pressed
+    detached: bool,
 }
 
 impl CursorController {
@@ -253,46 +480,79 @@ impl CursorController {
             row_offset: 0,
             column_offset: 0,
             render_x: 0,
+            soft_wrap: false,
+            line_offset: 0,
+            anchor: None,
+            detached: false,
         }
     }
 
-    fn get_render_x(&self, row: &Row) -> usize {
-        row.row_content[..self.cursor_x]
-            .chars()
-            .fold(0, |render_x, c| {
-                if c == '\t' {
-                    render_x + (TAB_STOP - 1) - (render_x % TAB_STOP) + 1
-                } else {
-                    render_x + 1
-                }
-            })
+    fn position(&self) -> (usize, usize) {
+        (self.cursor_x, self.cursor_y)
+    }
+
+    // jumping somewhere drops the selection
+    fn set_position(&mut self, (x, y): (usize, usize)) {
+        self.cursor_x = x;
+        self.cursor_y = y;
+        self.anchor = None;
     }
 
     fn scroll(&mut self, editor_rows: &EditorRows) {
         self.render_x = 0;
         if self.cursor_y < editor_rows.number_of_rows() {
-            self.render_x = self.get_render_x(editor_rows.get_editor_row(self.cursor_y));
+            self.render_x = get_render_x(&editor_rows.get_row(self.cursor_y), self.cursor_x);
+        }
+        if self.detached {
+            return;
+        }
+        if self.soft_wrap {
+            return self.scroll_wrapped(editor_rows);
         }
+        self.line_offset = 0;
+        // a terminal too small to show any text still keeps the cursor row at the top
+        let screen_rows = self.screen_rows.max(1);
+        let screen_columns = self.screen_columns.max(1);
         self.row_offset = cmp::min(self.row_offset, self.cursor_y);
-        if self.cursor_y >= self.row_offset + self.screen_rows {
-            self.row_offset = self.cursor_y - self.screen_rows + 1;
+        if self.cursor_y >= self.row_offset + screen_rows {
+            self.row_offset = self.cursor_y - screen_rows + 1;
         }
         self.column_offset = cmp::min(self.column_offset, self.render_x);
-        if self.render_x >= self.column_offset + self.screen_columns {
-            self.column_offset = self.render_x - self.screen_columns + 1;
+        if self.render_x >= self.column_offset + screen_columns {
+            self.column_offset = self.render_x - screen_columns + 1;
+        }
+    }
+
+    // where the cursor is drawn in the text area, None when it is out of view
+    fn screen_position(&self, editor_rows: &EditorRows) -> Option<(usize, usize)> {
+        if self.soft_wrap {
+            return self.wrapped_screen_position(editor_rows);
         }
+        let x = self.render_x.checked_sub(self.column_offset)?;
+        let y = self.cursor_y.checked_sub(self.row_offset)?;
+        (x < self.screen_columns.max(1) && y < self.screen_rows).then_some((x, y))
     }
 
     fn move_cursor(&mut self, direction: KeyCode, editor_rows: &EditorRows) {
         let number_of_rows = editor_rows.number_of_rows();
+        // Up and Down keep the screen column, the byte offset means nothing on another row
+        let render_x = if self.cursor_y < number_of_rows {
+            get_render_x(&editor_rows.get_row(self.cursor_y), self.cursor_x)
+        } else {
+            0
+        };
 
         match direction {
+            KeyCode::Up if self.soft_wrap => self.move_screen_line(true, editor_rows),
+            KeyCode::Down if self.soft_wrap => self.move_screen_line(false, editor_rows),
             KeyCode::Up => {
                 self.cursor_y = self.cursor_y.saturating_sub(1);
+                self.cursor_x = editor_rows.column_to_x(self.cursor_y, render_x);
             }
             KeyCode::Left => {
                 if self.cursor_x != 0 {
-                    self.cursor_x -= 1;
+                    self.cursor_x =
+                        previous_grapheme(&editor_rows.get_row(self.cursor_y), self.cursor_x);
                 } else if self.cursor_y > 0 {
                     self.cursor_y -= 1;
                     self.cursor_x = editor_rows.get_row(self.cursor_y).len();
@@ -301,12 +561,14 @@ impl CursorController {
             KeyCode::Down => {
                 if self.cursor_y < number_of_rows {
                     self.cursor_y += 1;
+                    self.cursor_x = editor_rows.column_to_x(self.cursor_y, render_x);
                 }
             }
             KeyCode::Right => {
                 if self.cursor_y < number_of_rows {
-                    match self.cursor_x.cmp(&editor_rows.get_row(self.cursor_y).len()) {
-                        Ordering::Less => self.cursor_x += 1,
+                    let row = editor_rows.get_row(self.cursor_y);
+                    match self.cursor_x.cmp(&row.len()) {
+                        Ordering::Less => self.cursor_x = next_grapheme(&row, self.cursor_x),
                         Ordering::Equal => {
                             self.cursor_y += 1;
                             self.cursor_x = 0
@@ -378,21 +640,100 @@ struct Output {
     editor_rows: EditorRows,
     status_message: StatusMessage,
     dirty: u64,
+    history: History,
+    search_index: SearchIndex,
+    syntax_registry: SyntaxRegistry,
+    // the buffers not on screen and where the current one sits among them
+    buffers: Vec<Buffer>,
+    current_buffer: usize,
+    settings: Settings,
+    // the `--name=value` arguments, which override the settings files again on a reload
+    arguments: Vec<String>,
+    line_numbers: LineNumbers,
+    clipboard: Clipboard,
+    // when and where the last click was, to tell double clicks
+    last_click: Option<(Instant, (usize, usize))>,
+    // modal editing, None when keys work the modeless way
+    vim: Option<Vim>,
+    keymap: Keymap,
 }
 
 impl Output {
     fn new() -> Self {
         let win_size = terminal::size()
-            .map(|(x, y)| (x as usize, y as usize - 2))
+            .map(|(x, y)| (x as usize, (y as usize).saturating_sub(2)))
             .unwrap();
-        Self {
+        let (arguments, file_arguments): (Vec<String>, Vec<String>) =
+            env::args().skip(1).partition(|arg| arg.starts_with("--"));
+        let (settings, settings_errors) = Settings::load(&arguments);
+        let (syntax_registry, syntax_errors) = SyntaxRegistry::load();
+        let (keymap, keymap_errors) = Keymap::load();
+        let mut errors: Vec<String> = settings_errors
+            .iter()
+            .map(|error| format!("Setting not loaded: {}", error))
+            .collect();
+        let mut files = Vec::new();
+        for arg in file_arguments {
+            // "file:line:column" opens the file there
+            let (file, position) = goto::split_position(&arg);
+            match EditorRows::from_file(file.into(), &syntax_registry) {
+                Ok(editor_rows) => files.push((editor_rows, position)),
+                Err(err) => errors.push(format!("Could not open \"{}\": {}", file, err)),
+            }
+        }
+        errors.extend(
+            syntax_errors
+                .iter()
+                .map(|error| format!("Syntax definition not loaded: {}", error)),
+        );
+        errors.extend(
+            keymap_errors
+                .iter()
+                .map(|error| format!("Key binding not loaded: {}", error)),
+        );
+        let help = keymap.help();
+        // the loaded settings are applied over the defaults the same way a reload does
+        let defaults = Settings::default();
+        let mut output = Self {
             win_size,
             editor_contents: EditorContents::new(),
             cursor_controller: CursorController::new(win_size),
             editor_rows: EditorRows::new(),
-            status_message: StatusMessage::new("HELP: Ctrl-S to Save | Ctrl-Q to Quit ".into()),
+            status_message: StatusMessage::new(
+                join_errors(&errors).unwrap_or(help),
+                settings.message_timeout,
+            ),
             dirty: 0,
+            history: History::new(),
+            search_index: SearchIndex::new(),
+            syntax_registry,
+            buffers: Vec::new(),
+            current_buffer: 0,
+            line_numbers: defaults.line_numbers,
+            clipboard: Clipboard::new(SystemClipboard::parse(&defaults.clipboard)),
+            last_click: None,
+            vim: defaults.vim.then(Vim::new),
+            keymap,
+            settings: defaults,
+            arguments,
+        };
+        output.apply_settings(settings);
+        for (editor_rows, position) in files {
+            output.open_buffer(editor_rows);
+            if let Some(position) = position {
+                output.go_to_position(position);
+            }
         }
+        output.switch_buffer(0);
+        output
+    }
+
+    // lays the screen out for the new terminal size, the next refresh draws it all again
+    fn resize(&mut self, columns: u16, rows: u16) -> crossterm::Result<()> {
+        self.win_size = (columns as usize, (rows as usize).saturating_sub(2));
+        self.cursor_controller.screen_columns = self.win_size.0;
+        self.cursor_controller.screen_rows = self.win_size.1;
+        Self::clear_screen()
     }
 
     fn clear_screen() -> crossterm::Result<()> {
@@ -408,7 +749,7 @@ impl Output {
         .unwrap();
         if let Some(msg) = self.status_message.message() {
             self.editor_contents
-                .push_str(&msg[..cmp::min(self.win_size.0, msg.len())]);
+                .push_str(truncate_to_width(msg, self.win_size.0));
         }
     }
 
@@ -419,81 +760,199 @@ impl Output {
         if self.cursor_controller.cursor_y == 0 && self.cursor_controller.cursor_x == 0 {
             return;
         }
-        let row = self
-            .editor_rows
-            .get_editor_row_mut(self.cursor_controller.cursor_y);
-        if self.cursor_controller.cursor_x > 0 {
-            row.delete_char(self.cursor_controller.cursor_x - 1);
-            self.cursor_controller.cursor_x -= 1;
+        let before = self.cursor_controller.position();
+        let (x, y) = before;
+        let op = if x > 0 {
+            let row = self.editor_rows.get_row(y);
+            let at = previous_grapheme(&row, x);
+            let text = row[at..x].to_string();
+            self.cursor_controller.cursor_x = at;
+            EditOp::Delete { x: at, y, text }
         } else {
-            let previous_row_content = self
-                .editor_rows
-                .get_row(self.cursor_controller.cursor_y - 1);
-            self.cursor_controller.cursor_x = previous_row_content.len();
-            self.editor_rows
-                .join_adjacent_rows(self.cursor_controller.cursor_y);
+            self.cursor_controller.cursor_x = self.editor_rows.get_row(y - 1).len();
             self.cursor_controller.cursor_y -= 1;
+            EditOp::Delete {
+                x: self.cursor_controller.cursor_x,
+                y: y - 1,
+                text: "\n".into(),
+            }
+        };
+        self.record_edit(op, before);
+    }
+
+    // takes out the grapheme after the cursor, a run of these is one undo step like Backspace
+    fn delete_forward(&mut self) {
+        let before = self.cursor_controller.position();
+        // moving the controller itself doesn't seal the history the way Output::move_cursor does
+        self.cursor_controller
+            .move_cursor(KeyCode::Right, &self.editor_rows);
+        let after = self.cursor_controller.position();
+        self.cursor_controller.set_position(before);
+        if after == before || after.1 >= self.editor_rows.number_of_rows() {
+            return;
         }
-        self.dirty += 1;
+        let text = self.editor_rows.buffer.slice(before, after);
+        let (x, y) = before;
+        self.record_edit(EditOp::Delete { x, y, text }, before);
     }
 
     fn insert_newline(&mut self) {
-        if self.cursor_controller.cursor_x == 0 {
-            self.editor_rows
-                .insert_row(self.cursor_controller.cursor_y, String::new())
-        } else {
-            let current_row = self
-                .editor_rows
-                .get_editor_row_mut(self.cursor_controller.cursor_y);
-            let new_row_content = current_row.row_content[self.cursor_controller.cursor_x..].into();
-            current_row
-                .row_content
-                .truncate(self.cursor_controller.cursor_x);
-            EditorRows::render_row(current_row);
-            self.editor_rows
-                .insert_row(self.cursor_controller.cursor_y + 1, new_row_content);
+        if self.replace_selection("\n") {
+            return;
         }
+        let before = self.cursor_controller.position();
+        let op = if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
+            EditOp::InsertRow {
+                y: self.cursor_controller.cursor_y,
+            }
+        } else {
+            EditOp::Insert {
+                x: self.cursor_controller.cursor_x,
+                y: self.cursor_controller.cursor_y,
+                text: "\n".into(),
+            }
+        };
         self.cursor_controller.cursor_x = 0;
         self.cursor_controller.cursor_y += 1;
-        self.dirty += 1;
+        self.record_edit(op, before);
+    }
+
+    // a tab, or with soft tabs the spaces up to the next tab stop
+    fn insert_tab(&mut self) {
+        if !self.settings.soft_tabs {
+            return self.insert_char('\t');
+        }
+        let (x, y) = self.cursor_controller.position();
+        let render_x = if y < self.editor_rows.number_of_rows() {
+            get_render_x(&self.editor_rows.get_row(y), x)
+        } else {
+            0
+        };
+        (0..tab_stop() - render_x % tab_stop()).for_each(|_| self.insert_char(' '))
     }
 
     fn insert_char(&mut self, ch: char) {
+        if self.replace_selection(ch.encode_utf8(&mut [0; 4])) {
+            return;
+        }
         if self.cursor_controller.cursor_y == self.editor_rows.number_of_rows() {
-            self.editor_rows
-                .insert_row(self.editor_rows.number_of_rows(), String::new());
-            self.dirty += 1;
+            let before = self.cursor_controller.position();
+            self.record_edit(
+                EditOp::InsertRow {
+                    y: self.cursor_controller.cursor_y,
+                },
+                before,
+            );
         }
-        self.editor_rows
-            .get_editor_row_mut(self.cursor_controller.cursor_y)
-            .insert_char(self.cursor_controller.cursor_x, ch);
-        self.cursor_controller.cursor_x += 1;
+        let before = self.cursor_controller.position();
+        self.cursor_controller.cursor_x += ch.len_utf8();
+        self.record_edit(
+            EditOp::Insert {
+                x: before.0,
+                y: before.1,
+                text: ch.into(),
+            },
+            before,
+        );
+    }
+
+    // applies `op` to the rows and adds it to the history, the cursor is already moved
+    fn record_edit(&mut self, op: EditOp, cursor_before: (usize, usize)) {
+        self.cursor_controller.anchor = None;
+        self.editor_rows.apply(&op);
+        self.history
+            .record(op, cursor_before, self.cursor_controller.position());
         self.dirty += 1;
     }
 
+    fn undo(&mut self) {
+        match self.history.undo() {
+            None => self.status_message.set_message("Nothing to undo".into()),
+            Some(change) => {
+                change
+                    .inverse_ops()
+                    .for_each(|op| self.editor_rows.apply(&op));
+                self.cursor_controller.set_position(change.cursor_before());
+                self.update_dirty();
+            }
+        }
+    }
+
+    fn redo(&mut self) {
+        match self.history.redo() {
+            None => self.status_message.set_message("Nothing to redo".into()),
+            Some(change) => {
+                change.ops().for_each(|op| self.editor_rows.apply(op));
+                self.cursor_controller.set_position(change.cursor_after());
+                self.update_dirty();
+            }
+        }
+    }
+
+    // switches the buffer between LF and CRLF, applied to the whole file on the next save
+    fn toggle_line_ending(&mut self) {
+        let format = &mut self.editor_rows.buffer.format;
+        format.line_ending = match format.line_ending {
+            LineEnding::Lf => LineEnding::Crlf,
+            LineEnding::Crlf => LineEnding::Lf,
+        };
+        self.status_message
+            .set_message(format!("Line endings set to {}", format.line_ending.name()));
+        self.history.forget_saved();
+        self.dirty += 1;
+    }
+
+    // undoing or redoing back to the saved state clears "(modified)"
+    fn update_dirty(&mut self) {
+        if self.history.is_saved() {
+            self.dirty = 0
+        } else {
+            self.dirty += 1
+        }
+    }
+
     fn draw_status_bar(&mut self) {
         self.editor_contents
             .push_str(&style::Attribute::Reverse.to_string());
+        let buffer_info = if self.number_of_buffers() > 1 {
+            format!(
+                "[{}/{}] ",
+                self.current_buffer + 1,
+                self.number_of_buffers()
+            )
+        } else {
+            String::new()
+        };
+        let size = match &self.editor_rows.hex {
+            Some(hex) => format!("{} bytes", hex.len()),
+            None => format!("{} lines of code", self.editor_rows.number_of_rows()),
+        };
         let info = format!(
-            "{} {} -- {} lines of code",
-            self.editor_rows
-                .filename
-                .as_ref()
-                .and_then(|path| path.file_name())
-                .and_then(|name| name.to_str())
-                .unwrap_or("[Unknown file]"),
+            "{}{}{} {} -- {}",
+            self.vim_status(),
+            buffer_info,
+            self.editor_rows.file_name(),
             if self.dirty > 0 { "(modified)" } else { "" },
-            self.editor_rows.number_of_rows()
+            size
         );
-        let info_len = cmp::min(info.len(), self.win_size.0);
-        let line_info = format!(
-            "{}/{}",
-            self.cursor_controller.cursor_y + 1,
-            self.editor_rows.number_of_rows()
-        );
-        self.editor_contents.push_str(&info[..info_len]);
+        let info = truncate_to_width(&info, self.win_size.0);
+        let info_len = info.width();
+        let line_info = match &self.editor_rows.hex {
+            Some(hex) => format!("hex | {:#x}/{:#x}", hex.offset(), hex.len()),
+            None => format!(
+                "{} | {} | {}/{}",
+                self.editor_rows
+                    .syntax
+                    .as_ref()
+                    .map_or("no ft", |syntax| syntax.name.as_str()),
+                self.editor_rows.buffer.format.describe(),
+                self.cursor_controller.cursor_y + 1,
+                self.editor_rows.number_of_rows()
+            ),
+        };
+        self.editor_contents.push_str(info);
         for i in info_len..self.win_size.0 {
-            if self.win_size.0 - i == line_info.len() {
+            if self.win_size.0 - i == line_info.width() {
                 self.editor_contents.push_str(&line_info);
                 break;
             } else {
@@ -508,11 +967,18 @@ impl Output {
     fn draw_rows(&mut self) {
         let screen_rows = self.win_size.1;
         let screen_columns = self.win_size.0;
+        let gutter = self.gutter_width();
+        let text_columns = self.cursor_controller.screen_columns;
+        self.editor_rows
+            .trim_cache(self.cursor_controller.row_offset);
+        let (mut file_row, mut line) = (
+            self.cursor_controller.row_offset,
+            self.cursor_controller.line_offset,
+        );
         for i in 0..screen_rows {
-            let file_row = i + self.cursor_controller.row_offset;
             if file_row >= self.editor_rows.number_of_rows() {
                 if self.editor_rows.number_of_rows() == 0 && i == screen_rows / 3 {
-                    let mut welcome = format!("Editor for Juspay Round_B");
+                    let mut welcome = String::from("Editor for Juspay Round_B");
                     if welcome.len() > screen_columns {
                         welcome.truncate(screen_columns)
                     }
@@ -526,12 +992,26 @@ impl Output {
                 } else {
                     self.editor_contents.push('~');
                 }
+            } else if self.cursor_controller.soft_wrap {
+                let starts = wrap_columns(
+                    &self.editor_rows.get_editor_row(file_row).render,
+                    text_columns,
+                );
+                let start = starts[line.min(starts.len() - 1)];
+                let end = starts
+                    .get(line + 1)
+                    .map_or(start + text_columns, |end| *end);
+                self.draw_line_number((line == 0).then_some(file_row), gutter);
+                self.draw_row(file_row, start, end - start);
+                line += 1;
+                if line >= starts.len() {
+                    file_row += 1;
+                    line = 0;
+                }
             } else {
-                let row = self.editor_rows.get_render(file_row);
-                let column_offset = self.cursor_controller.column_offset;
-                let len = cmp::min(row.len().saturating_sub(column_offset), screen_columns);
-                let start = if len == 0 { 0 } else { column_offset };
-                self.editor_contents.push_str(&row[start..start + len])
+                self.draw_line_number(Some(file_row), gutter);
+                self.draw_row(file_row, self.cursor_controller.column_offset, text_columns);
+                file_row += 1;
             }
             queue!(
                 self.editor_contents,
@@ -542,24 +1022,148 @@ impl Output {
         }
     }
 
+    // draws the `screen_columns` columns of `render` starting at `column_offset`
+    fn draw_row(&mut self, file_row: usize, column_offset: usize, screen_columns: usize) {
+        let search_match = match &self.search_index.highlight {
+            Some((y, range)) if *y == file_row => range.clone(),
+            _ => 0..0,
+        };
+        let selected = self.selected_columns(file_row);
+        let row = self.editor_rows.get_editor_row(file_row);
+        let mut current_highlight = None;
+        let mut reversed = false;
+        // `column` is the screen column in `render`, `char_index` indexes `row.highlight`
+        let (mut column, mut char_index) = (0, 0);
+        for grapheme in row.render.graphemes(true) {
+            let start = column;
+            let highlight_index = char_index;
+            column += grapheme_width(grapheme);
+            char_index += grapheme.chars().count();
+            if start < column_offset && column <= column_offset {
+                continue;
+            }
+            if column > column_offset + screen_columns {
+                break;
+            }
+            let highlight = if search_match.contains(&start) {
+                HighlightType::SearchMatch
+            } else {
+                row.highlight
+                    .get(highlight_index)
+                    .copied()
+                    .unwrap_or(HighlightType::Normal)
+            };
+            if current_highlight != Some(highlight) {
+                current_highlight = Some(highlight);
+                queue!(
+                    self.editor_contents,
+                    style::SetColors(highlight.colors(self.settings.theme))
+                )
+                .unwrap();
+            }
+            if reversed != selected.contains(&start) {
+                reversed = !reversed;
+                queue!(
+                    self.editor_contents,
+                    style::SetAttribute(if reversed {
+                        style::Attribute::Reverse
+                    } else {
+                        style::Attribute::NoReverse
+                    })
+                )
+                .unwrap();
+            }
+            if start < column_offset {
+                // a wide grapheme cut by the left edge, only its visible half is blank
+                (column_offset..column).for_each(|_| self.editor_contents.push(' '));
+            } else {
+                self.editor_contents.push_str(grapheme);
+            }
+        }
+        if reversed {
+            queue!(
+                self.editor_contents,
+                style::SetAttribute(style::Attribute::NoReverse)
+            )
+            .unwrap();
+        }
+        queue!(self.editor_contents, style::ResetColor).unwrap();
+    }
+
     fn move_cursor(&mut self, direction: KeyCode) {
+        self.history.seal();
         self.cursor_controller
             .move_cursor(direction, &self.editor_rows);
     }
 
+    // moves the cursor, selecting the text moved over when `selecting`
+    fn move_selecting(&mut self, direction: KeyCode, selecting: bool) {
+        self.cursor_controller.mark_selection(selecting);
+        self.move_cursor(direction)
+    }
+
+    fn page(&mut self, up: bool, selecting: bool) {
+        self.cursor_controller.mark_selection(selecting);
+        /* the cursor first goes to the edge of the screen. With soft wrap a screenful is
+        counted in screen lines, so it moves from where it is instead */
+        if !self.cursor_controller.soft_wrap {
+            if up {
+                self.cursor_controller.cursor_y = self.cursor_controller.row_offset
+            } else {
+                self.cursor_controller.cursor_y = cmp::min(
+                    (self.win_size.1 + self.cursor_controller.row_offset).saturating_sub(1),
+                    self.editor_rows.number_of_rows(),
+                );
+            }
+        }
+        (0..self.win_size.1).for_each(|_| {
+            self.move_cursor(if up { KeyCode::Up } else { KeyCode::Down });
+        })
+    }
+
+    /* the next key press, for a question on screen. A resize while waiting lays the screen
+    out again and redraws it, pastes and clicks don't answer the question and are dropped */
+    fn read_key(&mut self) -> crossterm::Result<KeyEvent> {
+        loop {
+            match Reader.read_event()? {
+                Event::Key(key_event) => return Ok(key_event),
+                Event::Resize(columns, rows) => {
+                    self.resize(columns, rows)?;
+                    self.refresh_screen()?;
+                }
+                _ => {}
+            }
+        }
+    }
+
     fn refresh_screen(&mut self) -> crossterm::Result<()> {
-        self.cursor_controller.scroll(&self.editor_rows);
         queue!(self.editor_contents, cursor::Hide, cursor::MoveTo(0, 0))?;
-        self.draw_rows();
+        if self.editor_rows.hex.is_some() {
+            self.hex_scroll();
+            self.draw_hex_rows();
+        } else {
+            // the text is narrower by the line numbers, which scrolling has to know
+            self.cursor_controller.screen_columns = self.win_size.0 - self.gutter_width();
+            self.cursor_controller.scroll(&self.editor_rows);
+            self.draw_rows();
+        }
         self.draw_status_bar();
         self.draw_message_bar();
-        let cursor_x = self.cursor_controller.render_x - self.cursor_controller.column_offset;
-        let cursor_y = self.cursor_controller.cursor_y - self.cursor_controller.row_offset;
-        queue!(
-            self.editor_contents,
-            cursor::MoveTo(cursor_x as u16, cursor_y as u16),
-            cursor::Show
-        )?;
+        let cursor = match self.hex_cursor() {
+            Some(position) => Some(position),
+            None => self
+                .cursor_controller
+                .screen_position(&self.editor_rows)
+                .map(|(x, y)| (x + self.gutter_width(), y)),
+        };
+        // the cursor stays hidden while the view is scrolled away from it
+        if let Some((cursor_x, cursor_y)) = cursor {
+            queue!(
+                self.editor_contents,
+                cursor::MoveTo(cursor_x as u16, cursor_y as u16),
+                cursor::Show
+            )?;
+        }
         self.editor_contents.flush()
     }
 }
@@ -567,11 +1171,26 @@ impl Output {
 struct Reader;
 
 impl Reader {
-    fn read_key(&self) -> crossterm::Result<KeyEvent> {
+    // the next key press, paste, resize, click, drag or wheel step, other events are skipped
+    fn read_event(&self) -> crossterm::Result<Event> {
         loop {
             if event::poll(Duration::from_millis(500))? {
-                if let Event::Key(event) = event::read()? {
-                    return Ok(event);
+                match event::read()? {
+                    // some terminals report releasing a key too
+                    Event::Key(KeyEvent {
+                        kind: KeyEventKind::Release,
+                        ..
+                    }) => {}
+                    // moving the pointer without a button held reports nothing worth a redraw
+                    Event::Mouse(MouseEvent {
+                        kind: MouseEventKind::Moved | MouseEventKind::Up(_),
+                        ..
+                    }) => {}
+                    event @ (Event::Key(_)
+                    | Event::Paste(_)
+                    | Event::Resize(..)
+                    | Event::Mouse(_)) => return Ok(event),
+                    _ => {}
                 }
             }
         }
@@ -586,107 +1205,184 @@ struct Editor {
 
 impl Editor {
     fn new() -> Self {
+        let output = Output::new();
         Self {
             reader: Reader,
-            output: Output::new(),
-            quit_times: QUIT_TIMES,
+            quit_times: output.settings.quit_times,
+            output,
         }
     }
 
     fn process_keypress(&mut self) -> crossterm::Result<bool> {
-        match self.reader.read_key()? {
-            KeyEvent {
-                code: KeyCode::Char('q'),
-                modifiers: KeyModifiers::CONTROL,
-            } => {
-                if self.output.dirty > 0 && self.quit_times > 0 {
-                    self.output.status_message.set_message(format!(
-                        "WARNING!!! File has unsaved changes. Press Ctrl-Q {} more times to quit.",
-                        self.quit_times
-                    ));
-                    self.quit_times -= 1;
+        let event = self.reader.read_event()?;
+        // anything but the mouse brings the view back to the cursor
+        if !matches!(event, Event::Mouse(_)) {
+            self.output.cursor_controller.detached = false;
+        }
+        let key_event = match event {
+            // with bracketed paste the whole paste comes as one event, inserted in one go
+            Event::Paste(text) => {
+                self.output.paste_text(&text);
+                self.quit_times = self.output.settings.quit_times;
+                return Ok(true);
+            }
+            Event::Key(key_event) => key_event,
+            Event::Resize(columns, rows) => {
+                self.output.resize(columns, rows)?;
+                return Ok(true);
+            }
+            Event::Mouse(mouse_event) => {
+                self.output.mouse(mouse_event);
+                return Ok(true);
+            }
+            _ => return Ok(true),
+        };
+        if self.output.hex_keypress(key_event)? || self.output.vim_keypress(key_event)? {
+            self.quit_times = self.output.settings.quit_times;
+            return Ok(true);
+        }
+        let mut keys = vec![Key::from(key_event)];
+        let command = loop {
+            match self.output.keymap.lookup(&keys) {
+                Lookup::Command(command) => break command,
+                // the chord so far stays in the status bar until it is complete
+                Lookup::Prefix => {
+                    self.output
+                        .status_message
+                        .set_message(format!("{} -", keymap::chord_name(&keys)));
+                    self.output.refresh_screen()?;
+                    keys.push(Key::from(self.output.read_key()?));
+                }
+                Lookup::Unbound if keys.len() > 1 => {
+                    self.output
+                        .status_message
+                        .set_message(format!("{} is not bound", keymap::chord_name(&keys)));
                     return Ok(true);
                 }
-                return Ok(false);
-            }
-            KeyEvent {
-                code:
-                    direction @ (KeyCode::Up                // moving left, right, up and bolltom using arrow key 
-                    | KeyCode::Down
-                    | KeyCode::Left
-                    | KeyCode::Right
-                    | KeyCode::Home
-                    | KeyCode::End),
-                modifiers: KeyModifiers::NONE,
-            } => self.output.move_cursor(direction),
-            KeyEvent {
-                code: val @ (KeyCode::PageUp | KeyCode::PageDown),
-                modifiers: KeyModifiers::NONE,
-            } => {
-                if matches!(val, KeyCode::PageUp) {
-                    self.output.cursor_controller.cursor_y =
-                        self.output.cursor_controller.row_offset
-                } else {
-                    self.output.cursor_controller.cursor_y = cmp::min(
-                        self.output.win_size.1 + self.output.cursor_controller.row_offset - 1,
-                        self.output.editor_rows.number_of_rows(),
-                    );
+                // keys that aren't bound to anything type themselves
+                Lookup::Unbound => {
+                    if let KeyEvent {
+                        code: KeyCode::Char(ch),
+                        modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                        ..
+                    } = key_event
+                    {
+                        self.output.insert_char(ch);
+                        self.quit_times = self.output.settings.quit_times;
+                    }
+                    return Ok(true);
                 }
-                (0..self.output.win_size.1).for_each(|_| {
-                    self.output.move_cursor(if matches!(val, KeyCode::PageUp) {
-                        KeyCode::Up
+            }
+        };
+        if keys.len() > 1 {
+            self.output.status_message.set_message(String::new());
+        }
+        // the hex view has already handled what it does differently
+        if self.output.editor_rows.hex.is_some() && !command.in_any_buffer() {
+            return Ok(true);
+        }
+        match command {
+            Command::Quit => {
+                let unsaved = self.output.unsaved_buffers();
+                if unsaved > 0 && self.quit_times > 0 {
+                    let quit_key = self
+                        .output
+                        .keymap
+                        .keys_for(Command::Quit)
+                        .unwrap_or_default();
+                    self.output.status_message.set_message(if self.output.number_of_buffers() > 1 {
+                        format!(
+                            "WARNING!!! {} buffer(s) have unsaved changes. Press {} {} more times to quit.",
+                            unsaved, quit_key, self.quit_times
+                        )
                     } else {
-                        KeyCode::Down
+                        format!(
+                            "WARNING!!! File has unsaved changes. Press {} {} more times to quit.",
+                            quit_key, self.quit_times
+                        )
                     });
-                })
+                    self.quit_times -= 1;
+                    return Ok(true);
+                }
+                return Ok(false);
             }
-            KeyEvent {          // Adding events for saving the file as .txt
-                code: KeyCode::Char('s'),
-                modifiers: KeyModifiers::CONTROL,
-            } => {
+            Command::MoveLeft => self.output.move_selecting(KeyCode::Left, false),
+            Command::MoveRight => self.output.move_selecting(KeyCode::Right, false),
+            Command::MoveUp => self.output.move_selecting(KeyCode::Up, false),
+            Command::MoveDown => self.output.move_selecting(KeyCode::Down, false),
+            Command::MoveHome => self.output.move_selecting(KeyCode::Home, false),
+            Command::MoveEnd => self.output.move_selecting(KeyCode::End, false),
+            Command::PageUp => self.output.page(true, false),
+            Command::PageDown => self.output.page(false, false),
+            Command::SelectLeft => self.output.move_selecting(KeyCode::Left, true),
+            Command::SelectRight => self.output.move_selecting(KeyCode::Right, true),
+            Command::SelectUp => self.output.move_selecting(KeyCode::Up, true),
+            Command::SelectDown => self.output.move_selecting(KeyCode::Down, true),
+            Command::SelectHome => self.output.move_selecting(KeyCode::Home, true),
+            Command::SelectEnd => self.output.move_selecting(KeyCode::End, true),
+            Command::SelectPageUp => self.output.page(true, true),
+            Command::SelectPageDown => self.output.page(false, true),
+            Command::Save => {
                 /* modify */
-                if matches!(self.output.editor_rows.filename, None) {
+                if self.output.editor_rows.filename.is_none() {
                     let prompt = prompt!(&mut self.output, "Save as : {} (ESC to cancel)")
                         .map(|it| it.into());
-                    if let None = prompt {
+                    if prompt.is_none() {
                         self.output
                             .status_message
                             .set_message("Save Aborted".into());
                         return Ok(true);
                     }
-                    self.output.editor_rows.filename = prompt
-                }
-                self.output.editor_rows.save().map(|len| {
+                    self.output.editor_rows.filename = prompt;
                     self.output
+                        .editor_rows
+                        .select_syntax(&self.output.syntax_registry);
+                }
+                match self.output.editor_rows.save(self.output.settings.backup) {
+                    Ok(len) => {
+                        self.output
+                            .status_message
+                            .set_message(format!("{} bytes written to disk", len));
+                        self.output.dirty = 0;
+                        self.output.history.mark_saved()
+                    }
+                    Err(err) => self
+                        .output
                         .status_message
-                        .set_message(format!("{} bytes written to disk", len));
-                    self.output.dirty = 0
-                })?;
-            }
-            KeyEvent {
-                code: key @ (KeyCode::Backspace | KeyCode::Delete),             // for deleting the character 
-                modifiers: KeyModifiers::NONE,
-            } => {
-                if matches!(key, KeyCode::Delete) {
-                    self.output.move_cursor(KeyCode::Right)
+                        .set_message(format!("Can't save! I/O error: {}", err)),
                 }
-                self.output.delete_char()
-            }
-            KeyEvent {
-                code: KeyCode::Enter,
-                modifiers: KeyModifiers::NONE,
-            } => self.output.insert_newline(),
-            KeyEvent {
-                code: code @ (KeyCode::Char(..) | KeyCode::Tab),
-                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
-            } => self.output.insert_char(match code {
-                KeyCode::Tab => '\t',
-                KeyCode::Char(ch) => ch,
-                _ => unreachable!(),
-            }),
-            _ => {}
-        }
-        self.quit_times = QUIT_TIMES;
+            }
+            Command::Find => self.output.find()?,
+            Command::Replace => self.output.replace()?,
+            Command::Undo => self.output.undo(),
+            Command::Redo => self.output.redo(),
+            Command::Open => self.output.open_file()?,
+            Command::ToggleLineEnding => self.output.toggle_line_ending(),
+            Command::GoTo => self.output.go_to_prompt()?,
+            Command::Cut => self.output.cut(),
+            Command::Copy => self.output.copy(),
+            Command::Paste => self.output.paste(),
+            Command::PasteFromRing => self.output.paste_from_ring()?,
+            Command::ToggleWrap => self.output.toggle_soft_wrap(),
+            Command::ToggleLineNumbers => self.output.toggle_line_numbers(),
+            Command::ToggleVim => self.output.toggle_vim(),
+            Command::NextBuffer => self.output.next_buffer(),
+            Command::PreviousBuffer => self.output.previous_buffer(),
+            Command::ListBuffers => self.output.pick_buffer()?,
+            Command::ReloadSettings => self.output.reload_settings(),
+            // a selection is deleted as a whole
+            Command::DeleteBack | Command::DeleteForward if self.output.has_selection() => {
+                self.output.delete_selection()
+            }
+            Command::DeleteBack => self.output.delete_char(),
+            Command::DeleteForward => self.output.delete_forward(),
+            Command::Cancel => self.output.cursor_controller.mark_selection(false),
+            Command::InsertTab if self.output.has_selection() => self.output.indent(false),
+            Command::InsertTab => self.output.insert_tab(),
+            Command::Outdent => self.output.indent(true),
+            Command::Newline => self.output.insert_newline(),
+        }
+        self.quit_times = self.output.settings.quit_times;
         Ok(true)
     }
 
@@ -699,6 +1395,7 @@ impl Editor {
 fn main() -> crossterm::Result<()> {
     let _clean_up = CleanUp;
     terminal::enable_raw_mode()?;
+    execute!(stdout(), EnableBracketedPaste, EnableMouseCapture)?;
     let mut editor = Editor::new();
     while editor.run()? {}
     Ok(())
@@ -0,0 +1,112 @@
+use crate::{CursorController, EditorRows, Output};
+use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+
+// rows the view moves for one step of the wheel
+const WHEEL_LINES: usize = 3;
+// a second click on the same spot within this time selects the word there
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+impl CursorController {
+    // the buffer position drawn at (column, row) of the text area
+    fn position_at(&self, editor_rows: &EditorRows, column: usize, row: usize) -> (usize, usize) {
+        if self.soft_wrap {
+            return self.wrapped_position_at(editor_rows, column, row);
+        }
+        let y = (self.row_offset + row).min(editor_rows.number_of_rows());
+        (editor_rows.column_to_x(y, self.column_offset + column), y)
+    }
+
+    /* moves the view up or down without taking the cursor along. It stays detached from
+    the cursor until the next key */
+    fn scroll_view(&mut self, editor_rows: &EditorRows, up: bool) {
+        self.detached = true;
+        if self.soft_wrap {
+            return self.scroll_wrapped_view(editor_rows, up, WHEEL_LINES);
+        }
+        self.row_offset = if up {
+            self.row_offset.saturating_sub(WHEEL_LINES)
+        } else {
+            (self.row_offset + WHEEL_LINES).min(editor_rows.number_of_rows().saturating_sub(1))
+        };
+    }
+}
+
+// the word `x` is in as a byte range of `row`, empty when `x` is not on a word
+fn word_at(row: &str, x: usize) -> Range<usize> {
+    let is_word = |grapheme: &str| grapheme.chars().all(|ch| ch.is_alphanumeric() || ch == '_');
+    let graphemes: Vec<(usize, &str)> = row.grapheme_indices(true).collect();
+    let at = match graphemes.iter().position(|(start, _)| *start == x) {
+        Some(at) if is_word(graphemes[at].1) => at,
+        _ => return x..x,
+    };
+    let start = graphemes[..at]
+        .iter()
+        .rev()
+        .take_while(|(_, grapheme)| is_word(grapheme))
+        .last()
+        .map_or(x, |(start, _)| *start);
+    let end = graphemes[at..]
+        .iter()
+        .find(|(_, grapheme)| !is_word(grapheme))
+        .map_or(row.len(), |(start, _)| *start);
+    start..end
+}
+
+impl Output {
+    /* a click puts the cursor under the pointer, dragging selects, Shift-click extends the
+    selection and a double click selects a word. The wheel scrolls the view */
+    pub(crate) fn mouse(&mut self, event: MouseEvent) {
+        if self.editor_rows.hex.is_some() {
+            return;
+        }
+        let column = (event.column as usize).saturating_sub(self.gutter_width());
+        let row = event.row as usize;
+        match event.kind {
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => self
+                .cursor_controller
+                .scroll_view(&self.editor_rows, event.kind == MouseEventKind::ScrollUp),
+            MouseEventKind::Down(MouseButton::Left) if row < self.win_size.1 => {
+                let position = self
+                    .cursor_controller
+                    .position_at(&self.editor_rows, column, row);
+                let double_click = self
+                    .last_click
+                    .is_some_and(|(time, at)| at == position && time.elapsed() < DOUBLE_CLICK);
+                self.history.seal();
+                self.cursor_controller.detached = false;
+                if event.modifiers.contains(KeyModifiers::SHIFT) {
+                    self.cursor_controller.select_to(position);
+                } else if double_click {
+                    self.select_word(position);
+                } else {
+                    self.cursor_controller.set_position(position);
+                    self.cursor_controller.mark_selection(true);
+                }
+                // a third click starts over instead of counting as another double click
+                self.last_click = (!double_click).then(|| (Instant::now(), position));
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                // dragging onto the status bar reaches one row past the view, which scrolls
+                let row = row.min(self.win_size.1);
+                let position = self
+                    .cursor_controller
+                    .position_at(&self.editor_rows, column, row);
+                self.cursor_controller.detached = false;
+                self.cursor_controller.select_to(position);
+            }
+            _ => {}
+        }
+    }
+
+    fn select_word(&mut self, (x, y): (usize, usize)) {
+        if y >= self.editor_rows.number_of_rows() {
+            return;
+        }
+        let word = word_at(&self.editor_rows.get_row(y), x);
+        self.cursor_controller.set_position((word.end, y));
+        self.cursor_controller.anchor = Some((word.start, y));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{config, prompt, EditorRows, Output, Reader};
+use crossterm::event::*;
+use std::fs;
+use std::path::PathBuf;
+
+// replaces a leading `~` with the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), config::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/* completes the last component of `input` against the entries of its directory. Returns
+the input extended as far as all candidates agree, and the candidates when more than one
+is left. Directories end in '/' */
+fn complete_path(_: &Output, input: &str) -> (String, Vec<String>) {
+    if input == "~" {
+        return ("~/".into(), Vec::new());
+    }
+    let (dir, prefix) = match input.rfind('/') {
+        Some(at) => input.split_at(at + 1),
+        None => ("", input),
+    };
+    let entries = match fs::read_dir(if dir.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_home(dir)
+    }) {
+        Ok(entries) => entries,
+        Err(_) => return (input.into(), Vec::new()),
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.path().is_dir();
+            Some(if is_dir { name + "/" } else { name })
+        })
+        // hidden entries only show up once the prefix asks for them
+        .filter(|name| {
+            name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))
+        })
+        .collect();
+    candidates.sort();
+    let common = match candidates.split_first() {
+        None => return (input.into(), Vec::new()),
+        Some((first, rest)) => rest.iter().fold(first.as_str(), |common, name| {
+            let len = common
+                .char_indices()
+                .zip(name.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(name.len()), |((at, _), _)| at);
+            &common[..len]
+        }),
+    };
+    let completed = format!("{}{}", dir, common);
+    if candidates.len() == 1 {
+        (completed, Vec::new())
+    } else {
+        (completed, candidates)
+    }
+}
+
+impl Output {
+    pub(crate) fn open_file(&mut self) -> crossterm::Result<()> {
+        let path = match prompt!(
+            self,
+            "Open: {} (Tab to complete, ESC to cancel)",
+            complete = complete_path
+        ) {
+            None => {
+                self.status_message.set_message("Open aborted".into());
+                return Ok(());
+            }
+            Some(path) => expand_home(&path),
+        };
+        if let Some(at) = self.find_buffer(&path) {
+            self.switch_buffer(at);
+            return Ok(());
+        }
+        let exists = path.exists();
+        match EditorRows::from_file(path.clone(), &self.syntax_registry) {
+            Ok(editor_rows) => {
+                self.open_buffer(editor_rows);
+                self.status_message.set_message(format!(
+                    "{} \"{}\"",
+                    if exists { "Opened" } else { "New file" },
+                    path.display()
+                ));
+            }
+            Err(err) => self.status_message.set_message(format!(
+                "Could not open \"{}\": {}",
+                path.display(),
+                err
+            )),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/* runs `write` on a temporary file next to `path`, syncs it to disk and renames it over
+`path`, so the old contents stay intact until the new ones are complete. The file keeps
+its permissions and, where allowed, its owner. With `backup` the old file is first copied
+to `path~` */
+pub fn save_atomically(
+    path: &Path,
+    backup: bool,
+    write: impl FnOnce(&mut BufWriter<&File>) -> io::Result<usize>,
+) -> io::Result<usize> {
+    // saving through a symlink replaces the file it points to, not the link
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let metadata = fs::metadata(&path).ok();
+    if metadata
+        .as_ref()
+        .is_some_and(|it| it.permissions().readonly())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "file is read-only",
+        ));
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::other("not a file name"))?;
+    let temp = dir.join(format!(".{}.{}.tmp", name.to_string_lossy(), process::id()));
+    let result = write_temp(&temp, write, metadata.as_ref()).and_then(|len| {
+        if backup && metadata.is_some() {
+            fs::copy(&path, backup_path(&path))?;
+        }
+        fs::rename(&temp, &path)?;
+        sync_dir(&dir);
+        Ok(len)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn write_temp(
+    temp: &Path,
+    write: impl FnOnce(&mut BufWriter<&File>) -> io::Result<usize>,
+    metadata: Option<&fs::Metadata>,
+) -> io::Result<usize> {
+    let file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+    let mut writer = BufWriter::new(&file);
+    let len = write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    if let Some(metadata) = metadata {
+        file.set_permissions(metadata.permissions())?;
+        preserve_owner(temp, metadata);
+    }
+    file.sync_all()?;
+    Ok(len)
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = OsString::from(path);
+    backup.push("~");
+    backup.into()
+}
+
+#[cfg(unix)]
+fn preserve_owner(temp: &Path, metadata: &fs::Metadata) {
+    use std::os::unix::fs::{chown, MetadataExt};
+    // only root may hand the file to someone else, otherwise it stays with whoever saves it
+    let _ = chown(temp, Some(metadata.uid()), Some(metadata.gid()));
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_: &Path, _: &fs::Metadata) {}
+
+// makes the rename itself durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) {}
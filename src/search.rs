@@ -0,0 +1,377 @@
+use crate::history::EditOp;
+use crate::keymap::Command;
+use crate::{get_render_x, prompt, EditorRows, Output, Reader};
+use crossterm::event::*;
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+#[derive(Clone, Copy, PartialEq)]
+enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+pub struct SearchIndex {
+    // row and `render` columns of the current match, drawn highlighted by draw_rows
+    pub highlight: Option<(usize, Range<usize>)>,
+    regex: bool,
+    case_insensitive: bool,
+    error: Option<String>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self {
+            highlight: None,
+            regex: false,
+            case_insensitive: false,
+            error: None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.highlight = None;
+        self.error = None;
+    }
+
+    // Alt-R and Alt-C flip the pattern options while a search or replace prompt is open
+    fn toggle(&mut self, key_event: KeyEvent) {
+        match key_event {
+            KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.regex = !self.regex,
+            KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers: KeyModifiers::ALT,
+                ..
+            } => self.case_insensitive = !self.case_insensitive,
+            _ => {}
+        }
+    }
+
+    // shown in front of the prompt so the active options are visible while typing
+    fn label(&self) -> String {
+        let mut label = format!(
+            "[{} {}] ",
+            if self.regex { "regex" } else { "text" },
+            if self.case_insensitive {
+                "icase"
+            } else {
+                "case"
+            }
+        );
+        if let Some(error) = &self.error {
+            label.push_str(&format!("({}) ", error));
+        }
+        label
+    }
+
+    fn matcher(&self, pattern: &str) -> Result<Matcher, String> {
+        Matcher::new(pattern, self.regex, self.case_insensitive)
+    }
+}
+
+struct Matcher {
+    regex: Regex,
+    literal: bool,
+}
+
+impl Matcher {
+    // plain text patterns are escaped so both modes share the same regex engine
+    fn new(pattern: &str, is_regex: bool, case_insensitive: bool) -> Result<Self, String> {
+        let source = if is_regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+        RegexBuilder::new(&source)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map(|regex| Self {
+                regex,
+                literal: !is_regex,
+            })
+            .map_err(|err| match err {
+                // the syntax error is a multi-line report, its last line holds the reason
+                regex::Error::Syntax(message) => message
+                    .lines()
+                    .last()
+                    .unwrap_or("invalid pattern")
+                    .trim_start_matches("error: ")
+                    .to_string(),
+                err => err.to_string(),
+            })
+    }
+
+    // the first match starting at byte `at` or later, the text before it still counts for `^` or `\b`
+    fn find_at(&self, row: &str, at: usize) -> Option<Range<usize>> {
+        if at > row.len() {
+            return None;
+        }
+        self.regex.find_at(row, at).map(|it| it.range())
+    }
+
+    /* the match starting at each place in `row` where one does, so a match that begins
+    inside an earlier one is there too */
+    fn find_in_row(&self, row: &str) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        let mut at = 0;
+        while let Some(range) = self.find_at(row, at) {
+            at = next_char(row, range.start);
+            matches.push(range);
+        }
+        matches
+    }
+
+    // the text replacing the match at `at`, with `$1` / `${name}` expanded in regex mode
+    fn expand(&self, row: &str, at: usize, replacement: &str) -> String {
+        if self.literal {
+            return replacement.into();
+        }
+        let mut expanded = String::new();
+        if let Some(captures) = self.regex.captures_at(row, at) {
+            captures.expand(replacement, &mut expanded);
+        }
+        expanded
+    }
+}
+
+// byte offset just past the character at `at`, used to step over empty matches
+fn next_char(row: &str, at: usize) -> usize {
+    at + row[at..].chars().next().map_or(1, char::len_utf8)
+}
+
+/* walks the rows from (x, y) in `direction`, wrapping around the end of the file.
+`inclusive` also accepts a match starting exactly at (x, y) */
+fn find_match(
+    editor_rows: &EditorRows,
+    matcher: &Matcher,
+    (x, y): (usize, usize),
+    direction: SearchDirection,
+    inclusive: bool,
+) -> Option<(Range<usize>, usize)> {
+    let number_of_rows = editor_rows.number_of_rows();
+    if number_of_rows == 0 {
+        return None;
+    }
+    let (x, y) = if y >= number_of_rows { (0, 0) } else { (x, y) };
+    for i in 0..=number_of_rows {
+        let (current, matches) = match direction {
+            SearchDirection::Forward => {
+                let current = (y + i) % number_of_rows;
+                let row = editor_rows.get_row(current);
+                let found = if i == 0 {
+                    matcher.find_at(&row, if inclusive { x } else { next_char(&row, x) })
+                } else if i == number_of_rows {
+                    matcher
+                        .find_at(&row, 0)
+                        .filter(|it| it.start < x || (!inclusive && it.start == x))
+                } else {
+                    matcher.find_at(&row, 0)
+                };
+                (current, found)
+            }
+            SearchDirection::Backward => {
+                let current = (y + number_of_rows - i % number_of_rows) % number_of_rows;
+                let matches = matcher.find_in_row(&editor_rows.get_row(current));
+                let found = if i == 0 {
+                    matches.into_iter().rev().find(|it| it.start < x)
+                } else if i == number_of_rows {
+                    matches.into_iter().rev().find(|it| it.start >= x)
+                } else {
+                    matches.into_iter().next_back()
+                };
+                (current, found)
+            }
+        };
+        if let Some(range) = matches {
+            return Some((range, current));
+        }
+    }
+    None
+}
+
+impl Output {
+    pub(crate) fn find(&mut self) -> crossterm::Result<()> {
+        let cursor_controller = self.cursor_controller;
+        self.history.seal();
+        let keyword = prompt!(
+            self,
+            "Search: {} (Use ESC / Arrows / Enter, Alt-R regex, Alt-C case)",
+            label = |output: &Output| output.search_index.label(),
+            callback = Output::find_callback
+        );
+        match keyword {
+            None => self.cursor_controller = cursor_controller,
+            Some(keyword) => {
+                if let Err(error) = self.search_index.matcher(&keyword) {
+                    self.status_message
+                        .set_message(format!("Invalid pattern \"{}\": {}", keyword, error));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn find_callback(output: &mut Output, keyword: &str, key_event: KeyEvent) {
+        let (direction, inclusive) = match output.keymap.command(key_event) {
+            Some(Command::Cancel | Command::Newline) => {
+                output.search_index.reset();
+                return;
+            }
+            Some(Command::MoveDown | Command::MoveRight) => (SearchDirection::Forward, false),
+            Some(Command::MoveUp | Command::MoveLeft) => (SearchDirection::Backward, false),
+            _ => {
+                output.search_index.toggle(key_event);
+                (SearchDirection::Forward, true)
+            }
+        };
+        output.search_index.reset();
+        if keyword.is_empty() {
+            return;
+        }
+        let matcher = match output.search_index.matcher(keyword) {
+            Err(error) => {
+                output.search_index.error = Some(error);
+                return;
+            }
+            Ok(matcher) => matcher,
+        };
+        let position = output.cursor_controller.position();
+        if let Some((range, y)) = find_match(
+            &output.editor_rows,
+            &matcher,
+            position,
+            direction,
+            inclusive,
+        ) {
+            output.cursor_controller.set_position((range.start, y));
+            // makes scroll() bring the match to the top of the screen
+            output.cursor_controller.row_offset = output.editor_rows.number_of_rows();
+            let row = output.editor_rows.get_row(y);
+            output.search_index.highlight = Some((
+                y,
+                get_render_x(&row, range.start)..get_render_x(&row, range.end),
+            ));
+        }
+    }
+
+    pub(crate) fn replace(&mut self) -> crossterm::Result<()> {
+        let pattern = match prompt!(
+            self,
+            "Replace: {} (ESC to cancel, Alt-R regex, Alt-C case)",
+            label = |output: &Output| output.search_index.label(),
+            callback = |output: &mut Output, _: &str, key_event: KeyEvent| {
+                output.search_index.toggle(key_event)
+            }
+        ) {
+            None => {
+                self.status_message.set_message("Replace aborted".into());
+                return Ok(());
+            }
+            Some(pattern) => pattern,
+        };
+        let matcher = match self.search_index.matcher(&pattern) {
+            Err(error) => {
+                self.status_message
+                    .set_message(format!("Invalid pattern \"{}\": {}", pattern, error));
+                return Ok(());
+            }
+            Ok(matcher) => matcher,
+        };
+        let replacement = match prompt!(
+            self,
+            "Replace with: {} (ESC to cancel, $1 for capture groups in regex mode)",
+            allow_empty = true
+        ) {
+            None => {
+                self.status_message.set_message("Replace aborted".into());
+                return Ok(());
+            }
+            Some(replacement) => replacement,
+        };
+        let cursor_controller = self.cursor_controller;
+        self.history.seal();
+        let mut ops = Vec::new();
+        let mut count = 0;
+        let mut replace_all = false;
+        let (mut x, mut y) = (0, 0);
+        while y < self.editor_rows.number_of_rows() {
+            let row = self.editor_rows.get_row(y);
+            // searching on from `x` finds a match the replacement and the text after it make
+            let range = match matcher.find_at(&row, x) {
+                None => {
+                    x = 0;
+                    y += 1;
+                    continue;
+                }
+                Some(range) => range,
+            };
+            let matched = row[range.clone()].to_string();
+            let expanded = matcher.expand(&row, range.start, &replacement);
+            let skip_to = if range.is_empty() {
+                next_char(&row, range.end)
+            } else {
+                range.end
+            };
+            let render_range = get_render_x(&row, range.start)..get_render_x(&row, range.end);
+            self.cursor_controller.set_position((range.start, y));
+            if !replace_all {
+                self.search_index.highlight = Some((y, render_range));
+                self.status_message
+                    .set_message("Replace this occurrence? (y)es / (n)o / (a)ll / (q)uit".into());
+                self.refresh_screen()?;
+                match self.read_key()?.code {
+                    KeyCode::Char('y') => {}
+                    KeyCode::Char('a') => replace_all = true,
+                    KeyCode::Char('n') => {
+                        x = skip_to;
+                        continue;
+                    }
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    _ => continue,
+                }
+            }
+            let mut edits = Vec::new();
+            if !matched.is_empty() {
+                edits.push(EditOp::Delete {
+                    x: range.start,
+                    y,
+                    text: matched,
+                });
+            }
+            if !expanded.is_empty() {
+                edits.push(EditOp::Insert {
+                    x: range.start,
+                    y,
+                    text: expanded.clone(),
+                });
+            }
+            edits.iter().for_each(|op| self.editor_rows.apply(op));
+            ops.extend(edits);
+            count += 1;
+            x = range.start + expanded.len();
+            if range.is_empty() {
+                x = next_char(&self.editor_rows.get_row(y), x);
+            }
+        }
+        self.search_index.reset();
+        if ops.is_empty() {
+            self.cursor_controller = cursor_controller;
+        } else {
+            self.history.record_all(
+                ops,
+                cursor_controller.position(),
+                self.cursor_controller.position(),
+            );
+            self.dirty += 1;
+        }
+        self.status_message.set_message(format!(
+            "Replaced {} occurrence(s) of \"{}\"",
+            count, pattern
+        ));
+        Ok(())
+    }
+}
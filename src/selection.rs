@@ -0,0 +1,184 @@
+use crate::history::EditOp;
+use crate::{get_render_x, tab_stop, CursorController, EditorRows, Output};
+use std::ops::{Range, RangeInclusive};
+
+/* the selection runs from the anchor, set where Shift was first held down while moving,
+to the cursor. Both are (x, y) positions like the cursor's */
+
+// where the cursor ends up after typing `text` at (x, y)
+pub fn position_after((x, y): (usize, usize), text: &str) -> (usize, usize) {
+    match text.rsplit_once('\n') {
+        None => (x + text.len(), y),
+        Some((_, last_row)) => (last_row.len(), y + text.matches('\n').count()),
+    }
+}
+
+impl CursorController {
+    // starts a selection at the cursor when `selecting`, otherwise drops the current one
+    pub(crate) fn mark_selection(&mut self, selecting: bool) {
+        if !selecting {
+            self.anchor = None;
+        } else if self.anchor.is_none() {
+            self.anchor = Some(self.position());
+        }
+    }
+
+    // moves the cursor to (x, y), selecting from where it was unless a selection is going on
+    pub(crate) fn select_to(&mut self, (x, y): (usize, usize)) {
+        self.mark_selection(true);
+        self.cursor_x = x;
+        self.cursor_y = y;
+    }
+
+    // start and end of the selection in file order, None when nothing is selected
+    pub(crate) fn selection(
+        &self,
+        editor_rows: &EditorRows,
+    ) -> Option<((usize, usize), (usize, usize))> {
+        // the line past the end counts as the end of the last row
+        let clamp = |(x, y): (usize, usize)| match editor_rows.number_of_rows() {
+            0 => (0, 0),
+            rows if y >= rows => (editor_rows.get_row(rows - 1).len(), rows - 1),
+            _ => (x, y),
+        };
+        let anchor = clamp(self.anchor?);
+        let cursor = clamp(self.position());
+        let (start, end) = if (anchor.1, anchor.0) <= (cursor.1, cursor.0) {
+            (anchor, cursor)
+        } else {
+            (cursor, anchor)
+        };
+        (start != end).then_some((start, end))
+    }
+}
+
+impl Output {
+    pub(crate) fn has_selection(&self) -> bool {
+        self.cursor_controller
+            .selection(&self.editor_rows)
+            .is_some()
+    }
+
+    // the render columns of row `y` that are selected, for drawing
+    pub(crate) fn selected_columns(&self, y: usize) -> Range<usize> {
+        let selection = self
+            .visual_selection()
+            .or_else(|| self.cursor_controller.selection(&self.editor_rows));
+        let (start, end) = match selection {
+            Some((start, end)) if (start.1..=end.1).contains(&y) => (start, end),
+            _ => return 0..0,
+        };
+        let row = self.editor_rows.get_row(y);
+        let from = if y == start.1 {
+            get_render_x(&row, start.0)
+        } else {
+            0
+        };
+        let to = if y == end.1 {
+            get_render_x(&row, end.0)
+        } else {
+            usize::MAX
+        };
+        from..to
+    }
+
+    pub(crate) fn delete_selection(&mut self) {
+        self.replace_selection("");
+    }
+
+    /* puts `text` in place of the selection as a single undo step and leaves the cursor
+    after it. Returns false, changing nothing, when there is no selection */
+    pub(crate) fn replace_selection(&mut self, text: &str) -> bool {
+        let (start, end) = match self.cursor_controller.selection(&self.editor_rows) {
+            None => return false,
+            Some(selection) => selection,
+        };
+        let before = self.cursor_controller.position();
+        let mut ops = vec![EditOp::Delete {
+            x: start.0,
+            y: start.1,
+            text: self.editor_rows.buffer.slice(start, end),
+        }];
+        if !text.is_empty() {
+            ops.push(EditOp::Insert {
+                x: start.0,
+                y: start.1,
+                text: text.into(),
+            });
+        }
+        ops.iter().for_each(|op| self.editor_rows.apply(op));
+        let after = position_after(start, text);
+        self.cursor_controller.set_position(after);
+        self.history.record_all(ops, before, after);
+        self.dirty += 1;
+        true
+    }
+
+    // the selected rows or the cursor row, a selection ending at the start of a row stops above it
+    fn selected_rows(&self) -> RangeInclusive<usize> {
+        match self.cursor_controller.selection(&self.editor_rows) {
+            Some((start, end)) if end.0 == 0 && end.1 > start.1 => start.1..=end.1 - 1,
+            Some((start, end)) => start.1..=end.1,
+            None => self.cursor_controller.cursor_y..=self.cursor_controller.cursor_y,
+        }
+    }
+
+    /* adds a tab, or a tab stop's worth of spaces with soft tabs, in front of every selected
+    row that isn't empty, or when `outdent` takes away a leading tab or up to a tab stop of
+    leading spaces. The selection stays on the same text, and the whole change is one undo
+    step */
+    pub(crate) fn indent(&mut self, outdent: bool) {
+        let indent = if self.settings.soft_tabs {
+            " ".repeat(tab_stop())
+        } else {
+            "\t".to_string()
+        };
+        let mut ops = Vec::new();
+        for y in self.selected_rows() {
+            if y >= self.editor_rows.number_of_rows() {
+                break;
+            }
+            let row = self.editor_rows.get_row(y);
+            if outdent {
+                let spaces = row.len() - row.trim_start_matches(' ').len();
+                let text = if row.starts_with('\t') {
+                    "\t".to_string()
+                } else {
+                    " ".repeat(spaces.min(tab_stop()))
+                };
+                if !text.is_empty() {
+                    ops.push(EditOp::Delete { x: 0, y, text });
+                }
+            } else if !row.is_empty() {
+                ops.push(EditOp::Insert {
+                    x: 0,
+                    y,
+                    text: indent.clone(),
+                });
+            }
+        }
+        if ops.is_empty() {
+            return;
+        }
+        let before = self.cursor_controller.position();
+        let mut anchor = self.cursor_controller.anchor;
+        let mut cursor = before;
+        for op in &ops {
+            self.editor_rows.apply(op);
+            for (x, y) in anchor.iter_mut().chain([&mut cursor]) {
+                match op {
+                    // a position at the start of the row keeps the new indent after it
+                    EditOp::Insert { y: row, text, .. } if row == y && *x > 0 => *x += text.len(),
+                    EditOp::Delete { y: row, text, .. } if row == y => {
+                        *x = x.saturating_sub(text.len())
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.cursor_controller.set_position(cursor);
+        self.cursor_controller.anchor = anchor;
+        self.history.record_all(ops, before, cursor);
+        self.dirty += 1;
+    }
+}
@@ -0,0 +1,230 @@
+use crate::clipboard::SystemClipboard;
+use crate::gutter::LineNumbers;
+use crate::keymap::Keymap;
+use crate::syntax::Theme;
+use crate::vim::Vim;
+use crate::{config, join_errors, Output, TAB_STOP};
+use serde::Deserialize;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic;
+use std::time::Duration;
+use std::{env, fs, io};
+
+/* the settings come in layers, each overriding the one before: the defaults, `config.toml`
+in the config directory, the project's `.juspay.toml` and `--name=value` arguments, `--name`
+alone meaning true. A layer may leave out any of them:
+
+    tab-width = 4
+    soft-tabs = true
+    quit-times = 1
+    message-timeout = 10
+    line-numbers = "relative"
+    wrap = true
+    theme = "light"
+    backup = true
+    clipboard = "osc52"
+    vim = true
+*/
+
+// looked for in the working directory and the ones above it
+const PROJECT_FILE: &str = ".juspay.toml";
+
+// one layer, what it leaves out stays as the layers below have it
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct Layer {
+    tab_width: Option<usize>,
+    soft_tabs: Option<bool>,
+    quit_times: Option<u8>,
+    message_timeout: Option<u64>,
+    line_numbers: Option<LineNumbers>,
+    wrap: Option<bool>,
+    theme: Option<Theme>,
+    backup: Option<bool>,
+    clipboard: Option<String>,
+    vim: Option<bool>,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Settings {
+    pub tab_width: usize,
+    // Tab types spaces up to the next tab stop
+    pub soft_tabs: bool,
+    // times Quit has to be pressed again to leave with unsaved changes
+    pub quit_times: u8,
+    pub message_timeout: Duration,
+    pub line_numbers: LineNumbers,
+    pub wrap: bool,
+    pub theme: Theme,
+    // keeps the previous version of a file as `file~` on every save
+    pub backup: bool,
+    // "auto", "off", "osc52" or a command the copied text is piped to
+    pub clipboard: String,
+    pub vim: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            tab_width: 8,
+            soft_tabs: false,
+            quit_times: 3,
+            message_timeout: Duration::from_secs(5),
+            line_numbers: LineNumbers::Absolute,
+            wrap: false,
+            theme: Theme::Dark,
+            backup: false,
+            clipboard: "auto".into(),
+            vim: false,
+        }
+    }
+}
+
+// the project's settings file, if there is one
+fn project_file() -> Option<PathBuf> {
+    env::current_dir()
+        .ok()?
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+// what comes after "=" in an argument, a number or true/false when it reads as one
+fn argument_value(value: &str) -> toml::Value {
+    if let Ok(number) = value.parse() {
+        toml::Value::Integer(number)
+    } else if let Ok(boolean) = value.parse() {
+        toml::Value::Boolean(boolean)
+    } else {
+        toml::Value::String(value.into())
+    }
+}
+
+impl Settings {
+    /* the settings of all the layers, `arguments` being the command-line ones. A file that
+    doesn't parse or an argument that doesn't is left out, with the reason in the errors */
+    pub fn load(arguments: &[String]) -> (Self, Vec<String>) {
+        let mut settings = Self::default();
+        let mut errors = Vec::new();
+        let files = [
+            config::config_dir().map(|dir| dir.join("config.toml")),
+            project_file(),
+        ];
+        for path in files.iter().flatten() {
+            settings.read(path, &mut errors);
+        }
+        for argument in arguments {
+            let (name, value) = match argument.split_once('=') {
+                Some((name, value)) => (name, argument_value(value)),
+                None => (argument.as_str(), toml::Value::Boolean(true)),
+            };
+            let mut table = toml::Table::new();
+            table.insert(name.trim_start_matches('-').into(), value);
+            match toml::Value::Table(table).try_into::<Layer>() {
+                Ok(layer) => settings.add(argument, layer, &mut errors),
+                Err(err) => errors.push(format!("{}: {}", argument, err.message())),
+            }
+        }
+        (settings, errors)
+    }
+
+    fn read(&mut self, path: &Path, errors: &mut Vec<String>) {
+        let name = path.display().to_string();
+        match fs::read_to_string(path) {
+            Ok(contents) => match toml::from_str::<Layer>(&contents) {
+                Ok(layer) => self.add(&name, layer, errors),
+                Err(err) => errors.push(format!("{}: {}", name, err.message())),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => errors.push(format!("{}: {}", name, err)),
+        }
+    }
+
+    fn add(&mut self, name: &str, layer: Layer, errors: &mut Vec<String>) {
+        match layer.tab_width {
+            Some(0) => errors.push(format!("{}: tab-width must be at least 1", name)),
+            Some(tab_width) => self.tab_width = tab_width,
+            None => {}
+        }
+        if let Some(soft_tabs) = layer.soft_tabs {
+            self.soft_tabs = soft_tabs;
+        }
+        if let Some(quit_times) = layer.quit_times {
+            self.quit_times = quit_times;
+        }
+        if let Some(seconds) = layer.message_timeout {
+            self.message_timeout = Duration::from_secs(seconds);
+        }
+        if let Some(line_numbers) = layer.line_numbers {
+            self.line_numbers = line_numbers;
+        }
+        if let Some(wrap) = layer.wrap {
+            self.wrap = wrap;
+        }
+        if let Some(theme) = layer.theme {
+            self.theme = theme;
+        }
+        if let Some(backup) = layer.backup {
+            self.backup = backup;
+        }
+        if let Some(clipboard) = layer.clipboard {
+            self.clipboard = clipboard;
+        }
+        if let Some(vim) = layer.vim {
+            self.vim = vim;
+        }
+    }
+}
+
+impl Output {
+    /* makes the new settings take effect. Line numbers, wrap and vim mode can be toggled
+    while editing, so they are only changed when their setting is */
+    pub(crate) fn apply_settings(&mut self, settings: Settings) {
+        let old = mem::replace(&mut self.settings, settings);
+        let new = self.settings.clone();
+        TAB_STOP.store(new.tab_width, atomic::Ordering::Relaxed);
+        if new.tab_width != old.tab_width {
+            // the rendered rows have their tabs expanded to the old width
+            self.for_each_buffer(|editor_rows, _| editor_rows.cache.clear());
+        }
+        if new.wrap != old.wrap {
+            self.for_each_buffer(|_, cursor_controller| {
+                cursor_controller.soft_wrap = new.wrap;
+                cursor_controller.line_offset = 0;
+                cursor_controller.column_offset = 0;
+            });
+        }
+        if new.line_numbers != old.line_numbers {
+            self.line_numbers = new.line_numbers;
+        }
+        if new.vim != old.vim && new.vim != self.vim.is_some() {
+            self.cursor_controller.mark_selection(false);
+            self.vim = new.vim.then(Vim::new);
+        }
+        if new.clipboard != old.clipboard {
+            self.clipboard
+                .set_system(SystemClipboard::parse(&new.clipboard));
+        }
+        self.status_message.timeout = new.message_timeout;
+    }
+
+    // reads the settings and key bindings again, keeping the command-line overrides
+    pub(crate) fn reload_settings(&mut self) {
+        let (settings, settings_errors) = Settings::load(&self.arguments);
+        let (keymap, keymap_errors) = Keymap::load();
+        self.apply_settings(settings);
+        self.keymap = keymap;
+        let errors: Vec<String> = settings_errors
+            .iter()
+            .map(|error| format!("Setting not loaded: {}", error))
+            .chain(
+                keymap_errors
+                    .iter()
+                    .map(|error| format!("Key binding not loaded: {}", error)),
+            )
+            .collect();
+        self.status_message
+            .set_message(join_errors(&errors).unwrap_or_else(|| "Settings reloaded".into()));
+    }
+}
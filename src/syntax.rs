@@ -0,0 +1,270 @@
+use crate::{config, Row};
+use crossterm::style::{Color, Colors};
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HighlightType {
+    Normal,
+    Number,
+    String,
+    Comment,
+    MultilineComment,
+    Keyword,
+    Type,
+    SearchMatch,
+}
+
+// the colors of highlighted text, picked with the `theme` setting
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Dark,
+    // darker colors that stay readable on a light background
+    Light,
+    // no colors but for search matches
+    Plain,
+}
+
+impl HighlightType {
+    pub fn colors(self, theme: Theme) -> Colors {
+        let (number, string, comment, keyword, kind) = match theme {
+            Theme::Dark => (
+                Color::Cyan,
+                Color::Green,
+                Color::DarkGrey,
+                Color::Yellow,
+                Color::Magenta,
+            ),
+            Theme::Light => (
+                Color::DarkCyan,
+                Color::DarkGreen,
+                Color::DarkGrey,
+                Color::DarkBlue,
+                Color::DarkMagenta,
+            ),
+            Theme::Plain => (
+                Color::Reset,
+                Color::Reset,
+                Color::Reset,
+                Color::Reset,
+                Color::Reset,
+            ),
+        };
+        let foreground = match self {
+            HighlightType::Normal => Color::Reset,
+            HighlightType::Number => number,
+            HighlightType::String => string,
+            HighlightType::Comment | HighlightType::MultilineComment => comment,
+            HighlightType::Keyword => keyword,
+            HighlightType::Type => kind,
+            HighlightType::SearchMatch => Color::Black,
+        };
+        let background = match self {
+            HighlightType::SearchMatch => Color::Yellow,
+            _ => Color::Reset,
+        };
+        Colors::new(foreground, background)
+    }
+}
+
+// describes how one language is highlighted, deserialized from a TOML file
+#[derive(Deserialize)]
+pub struct Syntax {
+    pub name: String,
+    pub extensions: Vec<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub types: Vec<String>,
+    pub comment_start: Option<String>,
+    pub multiline_comment: Option<(String, String)>,
+    #[serde(default)]
+    pub string_quotes: Vec<char>,
+    #[serde(default = "default_numbers")]
+    pub numbers: bool,
+}
+
+fn default_numbers() -> bool {
+    true
+}
+
+const BUILT_IN: [(&str, &str); 7] = [
+    ("rust.toml", include_str!("../syntax/rust.toml")),
+    ("python.toml", include_str!("../syntax/python.toml")),
+    ("json.toml", include_str!("../syntax/json.toml")),
+    ("toml.toml", include_str!("../syntax/toml.toml")),
+    ("markdown.toml", include_str!("../syntax/markdown.toml")),
+    ("c.toml", include_str!("../syntax/c.toml")),
+    ("shell.toml", include_str!("../syntax/shell.toml")),
+];
+
+pub struct SyntaxRegistry {
+    syntaxes: Vec<Rc<Syntax>>,
+}
+
+impl SyntaxRegistry {
+    // the built-in definitions, then every `<config dir>/syntax/<name>.toml`. A user file
+    // replaces the built-in language of the same name. Files that fail to load are
+    // skipped and reported in the returned list
+    pub fn load() -> (Self, Vec<String>) {
+        let mut registry = Self {
+            syntaxes: Vec::new(),
+        };
+        let mut errors = Vec::new();
+        for (name, contents) in BUILT_IN {
+            registry.add(name, contents, &mut errors);
+        }
+        let dir = config::config_dir().map(|dir| dir.join("syntax"));
+        if let Some(Ok(entries)) = dir.map(fs::read_dir) {
+            let mut paths: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|it| it.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+                .collect();
+            paths.sort();
+            for path in paths {
+                let name = path.display().to_string();
+                match fs::read_to_string(&path) {
+                    Ok(contents) => registry.add(&name, &contents, &mut errors),
+                    Err(err) => errors.push(format!("{}: {}", name, err)),
+                }
+            }
+        }
+        (registry, errors)
+    }
+
+    fn add(&mut self, name: &str, contents: &str, errors: &mut Vec<String>) {
+        match toml::from_str::<Syntax>(contents) {
+            Ok(syntax) => {
+                self.syntaxes.retain(|it| it.name != syntax.name);
+                self.syntaxes.push(Rc::new(syntax));
+            }
+            Err(err) => errors.push(format!("{}: {}", name, err.message())),
+        }
+    }
+
+    pub fn find(&self, extension: &str) -> Option<Rc<Syntax>> {
+        self.syntaxes
+            .iter()
+            .rev()
+            .find(|syntax| syntax.matches(extension))
+            .cloned()
+    }
+}
+
+impl Syntax {
+    pub fn matches(&self, extension: &str) -> bool {
+        self.extensions.iter().any(|it| it == extension)
+    }
+
+    /* fills `row.highlight` with one entry per character of `render`. `in_comment` tells
+    whether the previous row left a multi-line comment open */
+    pub fn highlight(&self, row: &mut Row, in_comment: bool) {
+        let chars: Vec<char> = row.render.chars().collect();
+        let starts_with = |at: usize, pattern: &str| {
+            let pattern: Vec<char> = pattern.chars().collect();
+            !pattern.is_empty() && chars[at..].starts_with(&pattern)
+        };
+        let mut highlight = vec![HighlightType::Normal; chars.len()];
+        let mut in_comment = in_comment;
+        let mut in_string: Option<char> = None;
+        let mut previous_separator = true;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let previous_highlight = if i > 0 {
+                highlight[i - 1]
+            } else {
+                HighlightType::Normal
+            };
+            if in_string.is_none() && !in_comment {
+                if let Some(comment_start) = &self.comment_start {
+                    if starts_with(i, comment_start) {
+                        highlight[i..].fill(HighlightType::Comment);
+                        break;
+                    }
+                }
+            }
+            if let (Some((start, end)), None) = (&self.multiline_comment, in_string) {
+                if in_comment {
+                    highlight[i] = HighlightType::MultilineComment;
+                    if starts_with(i, end) {
+                        let len = end.chars().count();
+                        highlight[i..i + len].fill(HighlightType::MultilineComment);
+                        i += len;
+                        in_comment = false;
+                        previous_separator = true;
+                    } else {
+                        i += 1;
+                    }
+                    continue;
+                } else if starts_with(i, start) {
+                    let len = start.chars().count();
+                    highlight[i..i + len].fill(HighlightType::MultilineComment);
+                    i += len;
+                    in_comment = true;
+                    continue;
+                }
+            }
+            if let Some(quote) = in_string {
+                highlight[i] = HighlightType::String;
+                if c == '\\' && i + 1 < chars.len() {
+                    highlight[i + 1] = HighlightType::String;
+                    i += 2;
+                    continue;
+                }
+                if c == quote {
+                    in_string = None;
+                }
+                i += 1;
+                previous_separator = true;
+                continue;
+            } else if self.string_quotes.contains(&c) {
+                in_string = Some(c);
+                highlight[i] = HighlightType::String;
+                i += 1;
+                continue;
+            }
+            if self.numbers
+                && ((c.is_ascii_digit() && previous_separator)
+                    || (previous_highlight == HighlightType::Number
+                        && (c.is_alphanumeric() || c == '.' || c == '_')))
+            {
+                highlight[i] = HighlightType::Number;
+                i += 1;
+                previous_separator = false;
+                continue;
+            }
+            if previous_separator {
+                let word_len = chars[i..]
+                    .iter()
+                    .take_while(|it| it.is_alphanumeric() || **it == '_')
+                    .count();
+                if word_len > 0 {
+                    let word: String = chars[i..i + word_len].iter().collect();
+                    let word_type = if self.keywords.contains(&word) {
+                        HighlightType::Keyword
+                    } else if self.types.contains(&word) {
+                        HighlightType::Type
+                    } else {
+                        HighlightType::Normal
+                    };
+                    highlight[i..i + word_len].fill(word_type);
+                    i += word_len;
+                    previous_separator = false;
+                    continue;
+                }
+            }
+            previous_separator = is_separator(c);
+            i += 1;
+        }
+        row.highlight = highlight;
+        row.ends_in_comment = in_comment;
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || ",.()+-/*=~%<>[];{}:&|!?^'\"#".contains(c)
+}
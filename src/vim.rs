@@ -0,0 +1,1364 @@
+use crate::clipboard::Clip;
+use crate::{get_render_x, next_grapheme, previous_grapheme, EditorRows, Output};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_segmentation::UnicodeSegmentation;
+
+/* vim-style modal editing, turned on with `--vim` or Alt-M. A command in normal mode is
+an optional count and a motion, or an operator (d, c, y, > or <) followed by the motion or
+text object it works on. Keys that aren't vim commands, like Ctrl-S, work as they always do */
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Normal,
+    Insert,
+    Visual,
+    // selects whole lines
+    VisualLine,
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+            Mode::VisualLine => "VISUAL LINE",
+        }
+    }
+}
+
+pub struct Vim {
+    mode: Mode,
+    // the keys of a command that isn't complete yet
+    pending: String,
+    // the keys of the last change, typed again by "."
+    last_change: Vec<KeyEvent>,
+    // the keys of the change going on, which lasts until insert mode is left
+    recording: Option<Vec<KeyEvent>>,
+}
+
+impl Vim {
+    pub fn new() -> Self {
+        Self {
+            mode: Mode::Normal,
+            pending: String::new(),
+            last_change: Vec::new(),
+            recording: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordStart,
+    WordEnd,
+    WordBack,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    // gg and G go to the line of the count when there is one
+    FirstLine,
+    LastLine,
+    // f, t, F and T. `before` stops next to the character instead of on it
+    Find {
+        ch: char,
+        before: bool,
+        backward: bool,
+    },
+}
+
+impl Motion {
+    // an operator given this motion works on whole lines
+    fn linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine
+        )
+    }
+
+    // the character the motion stops on is taken in by an operator
+    fn inclusive(self) -> bool {
+        matches!(
+            self,
+            Motion::WordEnd
+                | Motion::Find {
+                    backward: false,
+                    ..
+                }
+        )
+    }
+}
+
+enum Target {
+    Motion(Motion),
+    // dd, cc and yy, or the rows of the selection in visual mode
+    Lines,
+    // iw, a", i( and so on
+    Object { ch: char, around: bool },
+    Selection,
+}
+
+enum Action {
+    Move(Motion),
+    Operate(char, Target),
+    // i, a, I, A, o or O
+    Insert(char),
+    Put { before: bool },
+    Undo,
+    Visual { lines: bool },
+    // o in visual mode goes to the other end of the selection
+    SwapEnds,
+    Repeat,
+    Search,
+}
+
+enum Parsed<T> {
+    // more keys are needed
+    Incomplete,
+    Invalid,
+    Done(T),
+}
+
+impl<T> Parsed<T> {
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> Parsed<U> {
+        match self {
+            Parsed::Incomplete => Parsed::Incomplete,
+            Parsed::Invalid => Parsed::Invalid,
+            Parsed::Done(done) => Parsed::Done(f(done)),
+        }
+    }
+}
+
+// what an operator works on
+enum Span {
+    Chars((usize, usize), (usize, usize)),
+    Lines(usize, usize),
+}
+
+// "12dw" -> (Some(12), "dw"). A leading 0 is the motion to the start of the row, not a count
+fn split_count(keys: &str) -> (Option<usize>, &str) {
+    if keys.starts_with('0') {
+        return (None, keys);
+    }
+    let digits = keys
+        .find(|ch: char| !ch.is_ascii_digit())
+        .unwrap_or(keys.len());
+    (keys[..digits].parse().ok(), &keys[digits..])
+}
+
+fn parse_motion(keys: &str) -> Parsed<Motion> {
+    let mut chars = keys.chars();
+    let motion = match chars.next() {
+        None => return Parsed::Incomplete,
+        Some('h') => Motion::Left,
+        Some('l' | ' ') => Motion::Right,
+        Some('j') => Motion::Down,
+        Some('k') => Motion::Up,
+        Some('w') => Motion::WordStart,
+        Some('e') => Motion::WordEnd,
+        Some('b') => Motion::WordBack,
+        Some('0') => Motion::LineStart,
+        Some('^') => Motion::FirstNonBlank,
+        Some('$') => Motion::LineEnd,
+        Some('G') => Motion::LastLine,
+        Some('g') => match chars.next() {
+            None => return Parsed::Incomplete,
+            Some('g') => Motion::FirstLine,
+            Some(_) => return Parsed::Invalid,
+        },
+        Some(find @ ('f' | 't' | 'F' | 'T')) => match chars.next() {
+            None => return Parsed::Incomplete,
+            Some(ch) => Motion::Find {
+                ch,
+                before: matches!(find, 't' | 'T'),
+                backward: matches!(find, 'F' | 'T'),
+            },
+        },
+        Some(_) => return Parsed::Invalid,
+    };
+    Parsed::Done(motion)
+}
+
+// a command typed in normal mode and its count
+fn parse(keys: &str) -> Parsed<(Option<usize>, Action)> {
+    let (count, rest) = split_count(keys);
+    let mut chars = rest.chars();
+    let action = match chars.next() {
+        None => return Parsed::Incomplete,
+        Some(operator @ ('d' | 'c' | 'y' | '>' | '<')) => {
+            // "2d3w" deletes six words
+            let (count_after, rest) = split_count(chars.as_str());
+            let count = count
+                .or(count_after)
+                .map(|_| count.unwrap_or(1) * count_after.unwrap_or(1));
+            let mut chars = rest.chars();
+            let target = match chars.next() {
+                None => return Parsed::Incomplete,
+                Some(ch) if ch == operator => Target::Lines,
+                Some(around @ ('i' | 'a')) => match chars.next() {
+                    None => return Parsed::Incomplete,
+                    Some(ch) => Target::Object {
+                        ch,
+                        around: around == 'a',
+                    },
+                },
+                Some(_) => {
+                    return parse_motion(rest)
+                        .map(|motion| (count, Action::Operate(operator, Target::Motion(motion))))
+                }
+            };
+            Action::Operate(operator, target)
+        }
+        Some('x') => Action::Operate('d', Target::Motion(Motion::Right)),
+        Some('X') => Action::Operate('d', Target::Motion(Motion::Left)),
+        Some('D') => Action::Operate('d', Target::Motion(Motion::LineEnd)),
+        Some('C') => Action::Operate('c', Target::Motion(Motion::LineEnd)),
+        Some('s') => Action::Operate('c', Target::Motion(Motion::Right)),
+        Some('S') => Action::Operate('c', Target::Lines),
+        Some('Y') => Action::Operate('y', Target::Lines),
+        Some(ch @ ('i' | 'a' | 'I' | 'A' | 'o' | 'O')) => Action::Insert(ch),
+        Some('p') => Action::Put { before: false },
+        Some('P') => Action::Put { before: true },
+        Some('u') => Action::Undo,
+        Some('v') => Action::Visual { lines: false },
+        Some('V') => Action::Visual { lines: true },
+        Some('.') => Action::Repeat,
+        Some('/') => Action::Search,
+        Some(_) => return parse_motion(rest).map(|motion| (count, Action::Move(motion))),
+    };
+    Parsed::Done((count, action))
+}
+
+// a command typed in visual mode, where operators work on the selection right away
+fn parse_visual(keys: &str) -> Parsed<(Option<usize>, Action)> {
+    let action = match keys {
+        "d" | "x" => Action::Operate('d', Target::Selection),
+        "c" | "s" => Action::Operate('c', Target::Selection),
+        "y" => Action::Operate('y', Target::Selection),
+        ">" => Action::Operate('>', Target::Selection),
+        "<" => Action::Operate('<', Target::Selection),
+        "D" | "X" => Action::Operate('d', Target::Lines),
+        "C" | "S" => Action::Operate('c', Target::Lines),
+        "Y" => Action::Operate('y', Target::Lines),
+        "o" => Action::SwapEnds,
+        "v" => Action::Visual { lines: false },
+        "V" => Action::Visual { lines: true },
+        _ => {
+            let (count, rest) = split_count(keys);
+            return parse_motion(rest).map(|motion| (count, Action::Move(motion)));
+        }
+    };
+    Parsed::Done((None, action))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Blank,
+    // an empty row counts as a word of its own
+    Empty,
+    Word,
+    Punctuation,
+}
+
+fn class_of(ch: char) -> Class {
+    if ch.is_whitespace() {
+        Class::Blank
+    } else if ch.is_alphanumeric() || ch == '_' {
+        Class::Word
+    } else {
+        Class::Punctuation
+    }
+}
+
+// the end of a row is a blank between it and the next one
+fn class_at(editor_rows: &EditorRows, (x, y): (usize, usize)) -> Class {
+    let row = editor_rows.get_row(y);
+    match row[x..].chars().next() {
+        Some(ch) => class_of(ch),
+        None if row.is_empty() => Class::Empty,
+        None => Class::Blank,
+    }
+}
+
+// the position after (x, y), going on to the next row from the end of one
+fn step_forward(editor_rows: &EditorRows, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+    let row = editor_rows.get_row(y);
+    if x < row.len() {
+        Some((next_grapheme(&row, x), y))
+    } else if y + 1 < editor_rows.number_of_rows() {
+        Some((0, y + 1))
+    } else {
+        None
+    }
+}
+
+fn step_back(editor_rows: &EditorRows, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+    if x > 0 {
+        Some((previous_grapheme(&editor_rows.get_row(y), x), y))
+    } else if y > 0 {
+        Some((editor_rows.get_row(y - 1).len(), y - 1))
+    } else {
+        None
+    }
+}
+
+// w: past the rest of the word and the blanks after it
+fn next_word_start(editor_rows: &EditorRows, mut at: (usize, usize)) -> (usize, usize) {
+    let class = class_at(editor_rows, at);
+    loop {
+        at = match step_forward(editor_rows, at) {
+            None => return at,
+            Some(next) => next,
+        };
+        if matches!(class, Class::Blank | Class::Empty) || class_at(editor_rows, at) != class {
+            break;
+        }
+    }
+    while class_at(editor_rows, at) == Class::Blank {
+        at = match step_forward(editor_rows, at) {
+            None => return at,
+            Some(next) => next,
+        };
+    }
+    at
+}
+
+// e: on to the last character of this word, or of the next one when already there
+fn next_word_end(editor_rows: &EditorRows, mut at: (usize, usize)) -> (usize, usize) {
+    loop {
+        at = match step_forward(editor_rows, at) {
+            None => return at,
+            Some(next) => next,
+        };
+        if !matches!(class_at(editor_rows, at), Class::Blank | Class::Empty) {
+            break;
+        }
+    }
+    let class = class_at(editor_rows, at);
+    while let Some(next) = step_forward(editor_rows, at) {
+        if class_at(editor_rows, next) != class {
+            break;
+        }
+        at = next;
+    }
+    at
+}
+
+// b: back to the first character of this word, or of the one before when already there
+fn previous_word_start(editor_rows: &EditorRows, mut at: (usize, usize)) -> (usize, usize) {
+    loop {
+        at = match step_back(editor_rows, at) {
+            None => return at,
+            Some(previous) => previous,
+        };
+        if class_at(editor_rows, at) != Class::Blank {
+            break;
+        }
+    }
+    let class = class_at(editor_rows, at);
+    if class == Class::Empty {
+        return at;
+    }
+    while let Some(previous) = step_back(editor_rows, at) {
+        if class_at(editor_rows, previous) != class {
+            break;
+        }
+        at = previous;
+    }
+    at
+}
+
+fn first_non_blank(row: &str) -> usize {
+    row.len() - row.trim_start().len()
+}
+
+// f, t, F and T: the `times`th `ch` after or before `x` in the row
+fn find_in_row(
+    row: &str,
+    x: usize,
+    ch: char,
+    before: bool,
+    backward: bool,
+    times: usize,
+) -> Option<usize> {
+    let mut buffer = [0; 4];
+    let ch = &*ch.encode_utf8(&mut buffer);
+    let found = if backward {
+        row[..x]
+            .grapheme_indices(true)
+            .rev()
+            .filter(|(_, grapheme)| *grapheme == ch)
+            .nth(times - 1)?
+            .0
+    } else {
+        let from = next_grapheme(row, x);
+        from + row[from..]
+            .grapheme_indices(true)
+            .filter(|(_, grapheme)| *grapheme == ch)
+            .nth(times - 1)?
+            .0
+    };
+    Some(match (before, backward) {
+        (false, _) => found,
+        (true, false) => previous_grapheme(row, found),
+        (true, true) => next_grapheme(row, found),
+    })
+}
+
+/* iw is the run of word characters, punctuation or blanks `x` is in. aw takes in the
+blanks after it as well, or the ones before it when there are none after */
+fn word_object(row: &str, x: usize, around: bool) -> Option<(usize, usize)> {
+    let graphemes: Vec<(usize, Class)> = row
+        .grapheme_indices(true)
+        .map(|(at, grapheme)| (at, class_of(grapheme.chars().next().unwrap_or(' '))))
+        .collect();
+    let at = graphemes.iter().position(|(start, _)| *start == x)?;
+    // the indices of the graphemes around `at` that are all of the same class
+    let run = |at: usize| {
+        let class = graphemes[at].1;
+        let before = graphemes[..at]
+            .iter()
+            .rev()
+            .take_while(|(_, other)| *other == class)
+            .count();
+        let after = graphemes[at..]
+            .iter()
+            .take_while(|(_, other)| *other == class)
+            .count();
+        (at - before, at + after)
+    };
+    let (mut start, mut end) = run(at);
+    if around {
+        let on_blank = graphemes[at].1 == Class::Blank;
+        if end < graphemes.len() && (on_blank || graphemes[end].1 == Class::Blank) {
+            end = run(end).1;
+        } else if !on_blank && start > 0 && graphemes[start - 1].1 == Class::Blank {
+            start = run(start - 1).0;
+        }
+    }
+    let offset = |index: usize| graphemes.get(index).map_or(row.len(), |(at, _)| *at);
+    Some((offset(start), offset(end)))
+}
+
+// i" and a": quotes pair up from the start of the row, the first pair not before `x` is taken
+fn quote_object(row: &str, x: usize, quote: char, around: bool) -> Option<(usize, usize)> {
+    let quotes: Vec<usize> = row.match_indices(quote).map(|(at, _)| at).collect();
+    let (open, close) = quotes
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .find(|(_, close)| *close >= x)?;
+    let width = quote.len_utf8();
+    Some(if around {
+        (open, close + width)
+    } else {
+        (open + width, close)
+    })
+}
+
+/* the first `wanted` bracket that isn't paired with an `other` one on the way, looking
+back from just before (x, y) or on from (x, y) */
+fn unmatched_bracket(
+    editor_rows: &EditorRows,
+    (x, y): (usize, usize),
+    wanted: char,
+    other: char,
+    backward: bool,
+) -> Option<(usize, usize)> {
+    let rows: Vec<usize> = if backward {
+        (0..=y).rev().collect()
+    } else {
+        (y..editor_rows.number_of_rows()).collect()
+    };
+    let mut depth = 0;
+    for row_y in rows {
+        let row = editor_rows.get_row(row_y);
+        let chars: Vec<(usize, char)> = match (backward, row_y == y) {
+            (true, true) => row[..x].char_indices().rev().collect(),
+            (true, false) => row.char_indices().rev().collect(),
+            (false, true) => row[x..]
+                .char_indices()
+                .map(|(at, ch)| (x + at, ch))
+                .collect(),
+            (false, false) => row.char_indices().collect(),
+        };
+        for (at, ch) in chars {
+            if ch == other {
+                depth += 1;
+            } else if ch == wanted {
+                if depth == 0 {
+                    return Some((at, row_y));
+                }
+                depth -= 1;
+            }
+        }
+    }
+    None
+}
+
+fn ordered(a: (usize, usize), b: (usize, usize)) -> ((usize, usize), (usize, usize)) {
+    if (a.1, a.0) <= (b.1, b.0) {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+impl Output {
+    fn vim(&mut self) -> &mut Vim {
+        self.vim.as_mut().expect("vim mode is on")
+    }
+
+    fn vim_mode(&self) -> Option<Mode> {
+        self.vim.as_ref().map(|vim| vim.mode)
+    }
+
+    pub(crate) fn toggle_vim(&mut self) {
+        self.cursor_controller.mark_selection(false);
+        self.vim = match self.vim {
+            Some(_) => None,
+            None => Some(Vim::new()),
+        };
+        self.status_message.set_message(
+            if self.vim.is_some() {
+                "Vim mode on"
+            } else {
+                "Vim mode off"
+            }
+            .into(),
+        );
+    }
+
+    // "-- NORMAL 2d -- " for the status bar, empty without vim mode
+    pub(crate) fn vim_status(&self) -> String {
+        match &self.vim {
+            None => String::new(),
+            Some(vim) if vim.pending.is_empty() => format!("-- {} -- ", vim.mode.name()),
+            Some(vim) => format!("-- {} {} -- ", vim.mode.name(), vim.pending),
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        if matches!(mode, Mode::Visual | Mode::VisualLine) {
+            self.cursor_controller.mark_selection(true);
+        } else {
+            self.cursor_controller.mark_selection(false);
+        }
+        self.vim().mode = mode;
+    }
+
+    fn row_len(&self, y: usize) -> usize {
+        self.editor_rows.get_row(y).len()
+    }
+
+    // the position just past the character at (x, y)
+    fn after(&self, (x, y): (usize, usize)) -> (usize, usize) {
+        (next_grapheme(&self.editor_rows.get_row(y), x), y)
+    }
+
+    // onto the character before the cursor, without going up to the row above
+    fn step_left(&mut self) {
+        let (x, y) = self.cursor_controller.position();
+        if x > 0 {
+            self.cursor_controller.cursor_x = previous_grapheme(&self.editor_rows.get_row(y), x);
+        }
+    }
+
+    // outside insert mode the cursor stays on a character, not after the last one of the row
+    fn clamp_to_row(&mut self) {
+        let number_of_rows = self.editor_rows.number_of_rows();
+        if number_of_rows == 0 {
+            self.cursor_controller.cursor_x = 0;
+            self.cursor_controller.cursor_y = 0;
+            return;
+        }
+        let y = self.cursor_controller.cursor_y.min(number_of_rows - 1);
+        let row = self.editor_rows.get_row(y);
+        if self.cursor_controller.cursor_x >= row.len() {
+            self.cursor_controller.cursor_x = previous_grapheme(&row, row.len());
+        }
+        self.cursor_controller.cursor_y = y;
+    }
+
+    // what visual mode has selected, in file order. The character under the cursor is part of it
+    pub(crate) fn visual_selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let mode = self.vim_mode()?;
+        // either end can be on the line past the end, where a click or PageDown leaves it
+        let clamp = |(x, y): (usize, usize)| match self.editor_rows.number_of_rows() {
+            0 => (0, 0),
+            rows if y >= rows => (self.row_len(rows - 1), rows - 1),
+            _ => (x, y),
+        };
+        let (start, end) = ordered(
+            clamp(self.cursor_controller.anchor?),
+            clamp(self.cursor_controller.position()),
+        );
+        match mode {
+            Mode::Visual => Some((start, self.after(end))),
+            Mode::VisualLine => Some(((0, start.1), (self.row_len(end.1), end.1))),
+            _ => None,
+        }
+    }
+
+    /* handles a key while vim mode is on. Returns false for the keys left to the modeless
+    editor: Ctrl and Alt keys, and in insert mode the ones that don't type */
+    pub(crate) fn vim_keypress(&mut self, key_event: KeyEvent) -> crossterm::Result<bool> {
+        let mode = match self.vim_mode() {
+            None => return Ok(false),
+            Some(Mode::Insert) => return Ok(self.insert_mode_key(key_event)),
+            Some(mode) => mode,
+        };
+        // a selection made with the mouse or Shift is taken over by visual mode
+        if mode == Mode::Normal {
+            if self.has_selection() {
+                self.set_mode(Mode::Visual);
+            } else {
+                self.cursor_controller.mark_selection(false);
+            }
+        }
+        // the modeless keys, the mouse and PageDown can leave the cursor past the end of the text
+        self.clamp_to_row();
+        let ch = match key_event {
+            KeyEvent {
+                code: KeyCode::Char('r'),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } => {
+                self.vim().pending.clear();
+                self.set_mode(Mode::Normal);
+                self.redo();
+                self.clamp_to_row();
+                return Ok(true);
+            }
+            KeyEvent {
+                code: KeyCode::Esc, ..
+            } => {
+                self.vim().pending.clear();
+                self.set_mode(Mode::Normal);
+                return Ok(true);
+            }
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => ch,
+            KeyEvent {
+                code,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => match code {
+                KeyCode::Left | KeyCode::Backspace => 'h',
+                KeyCode::Right => 'l',
+                KeyCode::Up => 'k',
+                KeyCode::Down | KeyCode::Enter => 'j',
+                KeyCode::Home => '0',
+                KeyCode::End => '$',
+                KeyCode::Delete => 'x',
+                KeyCode::Insert => 'i',
+                KeyCode::Tab => return Ok(true),
+                _ => return Ok(false),
+            },
+            _ => return Ok(false),
+        };
+        self.vim().pending.push(ch);
+        let keys = self.vim().pending.clone();
+        let parsed = if mode == Mode::Normal {
+            parse(&keys)
+        } else {
+            parse_visual(&keys)
+        };
+        match parsed {
+            Parsed::Incomplete => return Ok(true),
+            Parsed::Invalid => self.vim().pending.clear(),
+            Parsed::Done((count, action)) => {
+                self.vim().pending.clear();
+                self.history.seal();
+                let change = match &action {
+                    Action::Operate(operator, _) => *operator != 'y',
+                    Action::Insert(_) | Action::Put { .. } => true,
+                    _ => false,
+                };
+                if change && mode == Mode::Normal {
+                    self.vim().recording = Some(
+                        keys.chars()
+                            .map(|ch| KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE))
+                            .collect(),
+                    );
+                }
+                self.run_action(count, action)?;
+                // a change that went into insert mode goes on until it is left
+                if self.vim_mode() != Some(Mode::Insert) {
+                    if let Some(keys) = self.vim().recording.take() {
+                        self.vim().last_change = keys;
+                    }
+                }
+            }
+        }
+        if self.vim_mode() != Some(Mode::Insert) {
+            self.clamp_to_row();
+        }
+        Ok(true)
+    }
+
+    // typing in insert mode, returns false for the keys left to the modeless editor
+    fn insert_mode_key(&mut self, key_event: KeyEvent) -> bool {
+        match key_event {
+            KeyEvent {
+                code: KeyCode::Esc, ..
+            } => {
+                self.history.seal();
+                self.set_mode(Mode::Normal);
+                // like vim, leaving insert mode steps back onto the last character typed
+                self.step_left();
+                self.clamp_to_row();
+            }
+            KeyEvent {
+                code: KeyCode::Char(ch),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => self.insert_char(ch),
+            KeyEvent {
+                code: KeyCode::Tab,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => self.insert_tab(),
+            KeyEvent {
+                code: KeyCode::Enter,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => self.insert_newline(),
+            KeyEvent {
+                code: KeyCode::Backspace | KeyCode::Delete,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } if self.has_selection() => self.delete_selection(),
+            KeyEvent {
+                code: KeyCode::Backspace,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => self.delete_char(),
+            KeyEvent {
+                code: KeyCode::Delete,
+                modifiers: KeyModifiers::NONE,
+                ..
+            } => self.delete_forward(),
+            KeyEvent {
+                code:
+                    KeyCode::Up
+                    | KeyCode::Down
+                    | KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Home
+                    | KeyCode::End
+                    | KeyCode::PageUp
+                    | KeyCode::PageDown,
+                ..
+            } => {
+                // moving around ends the change "." repeats
+                self.vim().recording = None;
+                return false;
+            }
+            _ => return false,
+        }
+        let vim = self.vim();
+        if let Some(keys) = &mut vim.recording {
+            keys.push(key_event);
+        }
+        if vim.mode == Mode::Normal {
+            if let Some(keys) = vim.recording.take() {
+                vim.last_change = keys;
+            }
+        }
+        true
+    }
+
+    fn run_action(&mut self, count: Option<usize>, action: Action) -> crossterm::Result<()> {
+        match action {
+            Action::Move(motion) => {
+                if let Some((x, y)) = self.motion_target(motion, count) {
+                    self.cursor_controller.cursor_x = x;
+                    self.cursor_controller.cursor_y = y;
+                }
+            }
+            Action::Operate(operator, target) => {
+                if let Some(span) = self.span(operator, &target, count) {
+                    self.operate(operator, span);
+                }
+                if self.vim_mode() != Some(Mode::Insert) {
+                    self.set_mode(Mode::Normal);
+                }
+            }
+            Action::Insert(command) => self.start_insert(command),
+            Action::Put { before } => self.put(before, count.unwrap_or(1)),
+            Action::Undo => (0..count.unwrap_or(1)).for_each(|_| self.undo()),
+            Action::Visual { lines } => {
+                let mode = if lines {
+                    Mode::VisualLine
+                } else {
+                    Mode::Visual
+                };
+                if self.vim_mode() == Some(mode) {
+                    self.set_mode(Mode::Normal)
+                } else {
+                    self.set_mode(mode)
+                }
+            }
+            Action::SwapEnds => {
+                if let Some(anchor) = self.cursor_controller.anchor {
+                    self.cursor_controller.anchor = Some(self.cursor_controller.position());
+                    self.cursor_controller.cursor_x = anchor.0;
+                    self.cursor_controller.cursor_y = anchor.1;
+                }
+            }
+            Action::Repeat => {
+                let keys = self.vim().last_change.clone();
+                for key in keys {
+                    self.vim_keypress(key)?;
+                }
+            }
+            Action::Search => self.find()?,
+        }
+        Ok(())
+    }
+
+    // where `motion` goes from the cursor, None when it can't go anywhere
+    fn motion_target(&self, motion: Motion, count: Option<usize>) -> Option<(usize, usize)> {
+        let editor_rows = &self.editor_rows;
+        let last = editor_rows.number_of_rows().checked_sub(1)?;
+        let times = count.unwrap_or(1);
+        let (x, y) = self.cursor_controller.position();
+        let y = y.min(last);
+        let row = editor_rows.get_row(y);
+        let target = match motion {
+            Motion::Left => ((0..times).fold(x, |x, _| previous_grapheme(&row, x)), y),
+            Motion::Right => ((0..times).fold(x, |x, _| next_grapheme(&row, x)), y),
+            Motion::Up | Motion::Down => {
+                let to = if motion == Motion::Up {
+                    y.saturating_sub(times)
+                } else {
+                    (y + times).min(last)
+                };
+                (editor_rows.column_to_x(to, get_render_x(&row, x)), to)
+            }
+            Motion::WordStart => (0..times).fold((x, y), |at, _| next_word_start(editor_rows, at)),
+            Motion::WordEnd => (0..times).fold((x, y), |at, _| next_word_end(editor_rows, at)),
+            Motion::WordBack => {
+                (0..times).fold((x, y), |at, _| previous_word_start(editor_rows, at))
+            }
+            Motion::LineStart => (0, y),
+            Motion::FirstNonBlank => (first_non_blank(&row), y),
+            Motion::LineEnd => {
+                let to = (y + times - 1).min(last);
+                (editor_rows.get_row(to).len(), to)
+            }
+            Motion::FirstLine | Motion::LastLine => {
+                let to = match count {
+                    Some(line) => (line - 1).min(last),
+                    None if motion == Motion::FirstLine => 0,
+                    None => last,
+                };
+                (first_non_blank(&editor_rows.get_row(to)), to)
+            }
+            Motion::Find {
+                ch,
+                before,
+                backward,
+            } => (find_in_row(&row, x, ch, before, backward, times)?, y),
+        };
+        Some(target)
+    }
+
+    // the text or rows `operator` works on
+    fn span(&self, operator: char, target: &Target, count: Option<usize>) -> Option<Span> {
+        let last = self.editor_rows.number_of_rows().checked_sub(1)?;
+        let cursor = self.cursor_controller.position();
+        let span = match target {
+            Target::Selection if self.vim_mode() == Some(Mode::VisualLine) => {
+                let (start, end) = self.visual_selection()?;
+                Span::Lines(start.1, end.1)
+            }
+            Target::Selection => {
+                let (start, end) = self.visual_selection()?;
+                Span::Chars(start, end)
+            }
+            Target::Lines if self.visual_selection().is_some() => {
+                let (start, end) = self.visual_selection()?;
+                Span::Lines(start.1, end.1)
+            }
+            Target::Lines => Span::Lines(cursor.1, (cursor.1 + count.unwrap_or(1) - 1).min(last)),
+            Target::Object { ch, around } => {
+                return self.text_object(*ch, *around);
+            }
+            Target::Motion(motion) => {
+                // cw changes the word and leaves the blanks after it, like ce
+                let motion = match motion {
+                    Motion::WordStart
+                        if operator == 'c'
+                            && class_at(&self.editor_rows, cursor) != Class::Blank =>
+                    {
+                        Motion::WordEnd
+                    }
+                    motion => *motion,
+                };
+                let to = self.motion_target(motion, count)?;
+                let (start, mut end) = ordered(cursor, to);
+                if motion.linewise() {
+                    return Some(Span::Lines(start.1, end.1));
+                }
+                if motion.inclusive() {
+                    end = self.after(end);
+                }
+                // dw on the last word of a row stops at the end of that row
+                if motion == Motion::WordStart
+                    && end.1 > start.1
+                    && self.editor_rows.get_row(end.1)[..end.0].trim().is_empty()
+                {
+                    end = (self.row_len(end.1 - 1), end.1 - 1);
+                }
+                Span::Chars(start, end)
+            }
+        };
+        Some(span)
+    }
+
+    // the text object `ch` at the cursor: a word, quoted text or what brackets enclose
+    fn text_object(&self, ch: char, around: bool) -> Option<Span> {
+        let (x, y) = self.cursor_controller.position();
+        let row = self.editor_rows.get_row(y);
+        let (open, close) = match ch {
+            'w' => {
+                let (start, end) = word_object(&row, x, around)?;
+                return Some(Span::Chars((start, y), (end, y)));
+            }
+            '"' | '\'' | '`' => {
+                let (start, end) = quote_object(&row, x, ch, around)?;
+                return Some(Span::Chars((start, y), (end, y)));
+            }
+            '(' | ')' | 'b' => ('(', ')'),
+            '{' | '}' | 'B' => ('{', '}'),
+            '[' | ']' => ('[', ']'),
+            '<' | '>' => ('<', '>'),
+            _ => return None,
+        };
+        // on the opening bracket the cursor is already inside the pair
+        let start = if row[x..].starts_with(open) {
+            (x, y)
+        } else {
+            unmatched_bracket(&self.editor_rows, (x, y), open, close, true)?
+        };
+        let end = unmatched_bracket(
+            &self.editor_rows,
+            (start.0 + 1, start.1),
+            close,
+            open,
+            false,
+        )?;
+        if around {
+            return Some(Span::Chars(start, (end.0 + 1, end.1)));
+        }
+        // a block with the brackets on rows of their own leaves those rows alone
+        let open_row = self.editor_rows.get_row(start.1);
+        let close_row = self.editor_rows.get_row(end.1);
+        if end.1 > start.1 + 1
+            && open_row[start.0 + 1..].trim().is_empty()
+            && close_row[..end.0].trim().is_empty()
+        {
+            return Some(Span::Lines(start.1 + 1, end.1 - 1));
+        }
+        Some(Span::Chars((start.0 + 1, start.1), end))
+    }
+
+    /* d and c keep what they take out for pasting, y copies it, > and < indent the rows.
+    Each is one undo step */
+    fn operate(&mut self, operator: char, span: Span) {
+        let (start, end, lines) = match span {
+            Span::Chars(start, end) => (start, end, false),
+            Span::Lines(first, last) => ((0, first), (self.row_len(last), last), true),
+        };
+        let mut text = self.editor_rows.buffer.slice(start, end);
+        if lines {
+            text.push('\n');
+        }
+        match operator {
+            'y' => {
+                let cursor = self.cursor_controller.position();
+                self.keep_clip(Clip { text, lines }, "Yanked");
+                // yy leaves the cursor where it is, other yanks go to the start
+                self.cursor_controller
+                    .set_position(if lines && cursor.1 == start.1 {
+                        cursor
+                    } else {
+                        start
+                    });
+            }
+            '>' | '<' => {
+                self.cursor_controller.set_position(start);
+                self.cursor_controller.anchor = Some(end);
+                self.indent(operator == '<');
+                let first = start.1;
+                self.cursor_controller
+                    .set_position((first_non_blank(&self.editor_rows.get_row(first)), first));
+            }
+            _ => {
+                self.clipboard.push(Clip { text, lines });
+                let number_of_rows = self.editor_rows.number_of_rows();
+                // dd takes the line ending along, the one before when it is the last row
+                let (from, to) = match (lines && operator == 'd', start.1) {
+                    (true, _) if end.1 + 1 < number_of_rows => (start, (0, end.1 + 1)),
+                    (true, first) if first > 0 => ((self.row_len(first - 1), first - 1), end),
+                    _ => (start, end),
+                };
+                // the cursor goes back to `from` on undo
+                self.cursor_controller.set_position(from);
+                self.cursor_controller.anchor = Some(to);
+                self.delete_selection();
+                self.cursor_controller.set_position(start);
+                if operator == 'c' {
+                    self.set_mode(Mode::Insert);
+                } else if lines {
+                    let y = start
+                        .1
+                        .min(self.editor_rows.number_of_rows().saturating_sub(1));
+                    let x = match self.editor_rows.number_of_rows() {
+                        0 => 0,
+                        _ => first_non_blank(&self.editor_rows.get_row(y)),
+                    };
+                    self.cursor_controller.set_position((x, y));
+                }
+            }
+        }
+    }
+
+    fn start_insert(&mut self, command: char) {
+        let (x, y) = self.cursor_controller.position();
+        let row = if y < self.editor_rows.number_of_rows() {
+            self.editor_rows.get_row(y).to_string()
+        } else {
+            String::new()
+        };
+        match command {
+            'a' => self
+                .cursor_controller
+                .set_position((next_grapheme(&row, x), y)),
+            'I' => self
+                .cursor_controller
+                .set_position((first_non_blank(&row), y)),
+            'A' => self.cursor_controller.set_position((row.len(), y)),
+            'o' if y < self.editor_rows.number_of_rows() => {
+                self.cursor_controller.set_position((row.len(), y));
+                self.insert_newline();
+            }
+            'O' if y < self.editor_rows.number_of_rows() => {
+                self.cursor_controller.set_position((0, y));
+                self.insert_newline();
+                self.cursor_controller.set_position((0, y));
+            }
+            _ => {}
+        }
+        self.set_mode(Mode::Insert);
+    }
+
+    // p puts the last cut or yank after the cursor, or below the row for whole lines, P before
+    fn put(&mut self, before: bool, count: usize) {
+        let clip = match self.clipboard.last() {
+            None => {
+                self.status_message.set_message("Nothing to paste".into());
+                return;
+            }
+            Some(clip) => clip.clone(),
+        };
+        let text = clip.text.repeat(count);
+        let number_of_rows = self.editor_rows.number_of_rows();
+        let (x, y) = self.cursor_controller.position();
+        if clip.lines {
+            let y = if before {
+                y
+            } else {
+                (y + 1).min(number_of_rows)
+            };
+            self.cursor_controller.set_position((0, y));
+            self.paste_clip(Clip { text, lines: true });
+            let x = first_non_blank(&self.editor_rows.get_row(y));
+            self.cursor_controller.set_position((x, y));
+        } else {
+            let x = if !before && y < number_of_rows {
+                self.after((x, y)).0
+            } else {
+                x
+            };
+            self.cursor_controller.set_position((x, y));
+            self.insert_text(&text);
+            // the cursor ends up on the last character put in
+            self.step_left();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clipboard::{Clipboard, SystemClipboard};
+    use crate::gutter::LineNumbers;
+    use crate::history::History;
+    use crate::keymap::Keymap;
+    use crate::search::SearchIndex;
+    use crate::settings::Settings;
+    use crate::syntax::SyntaxRegistry;
+    use crate::{CursorController, EditorContents, StatusMessage};
+
+    fn output(rows: &[&str]) -> Output {
+        let mut editor_rows = EditorRows::new();
+        for (at, row) in rows.iter().enumerate() {
+            editor_rows.buffer.insert_row(at, row);
+        }
+        let settings = Settings::default();
+        Output {
+            win_size: (80, 10),
+            editor_contents: EditorContents::new(),
+            cursor_controller: CursorController::new((80, 10)),
+            editor_rows,
+            status_message: StatusMessage::new(String::new(), settings.message_timeout),
+            dirty: 0,
+            history: History::new(),
+            search_index: SearchIndex::new(),
+            syntax_registry: SyntaxRegistry::load().0,
+            buffers: Vec::new(),
+            current_buffer: 0,
+            settings,
+            arguments: Vec::new(),
+            line_numbers: LineNumbers::Absolute,
+            clipboard: Clipboard::new(SystemClipboard::Off),
+            last_click: None,
+            vim: Some(Vim::new()),
+            keymap: Keymap::load().0,
+        }
+    }
+
+    // types `keys`, "\x1b" being Esc
+    fn press(output: &mut Output, keys: &str) {
+        for ch in keys.chars() {
+            let key_event = match ch {
+                '\x1b' => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+                ch => KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE),
+            };
+            assert!(output.vim_keypress(key_event).unwrap(), "{:?}", ch);
+        }
+    }
+
+    fn rows(output: &Output) -> Vec<String> {
+        (0..output.editor_rows.number_of_rows())
+            .map(|at| output.editor_rows.get_row(at).into_owned())
+            .collect()
+    }
+
+    fn position(output: &Output) -> (usize, usize) {
+        output.cursor_controller.position()
+    }
+
+    #[test]
+    fn splits_counts() {
+        assert_eq!(split_count("12dw"), (Some(12), "dw"));
+        assert_eq!(split_count("dw"), (None, "dw"));
+        assert_eq!(split_count("0"), (None, "0"));
+        assert_eq!(split_count("10j"), (Some(10), "j"));
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert!(matches!(parse(""), Parsed::Incomplete));
+        assert!(matches!(parse("3"), Parsed::Incomplete));
+        assert!(matches!(parse("d"), Parsed::Incomplete));
+        assert!(matches!(parse("di"), Parsed::Incomplete));
+        assert!(matches!(parse("f"), Parsed::Incomplete));
+        assert!(matches!(parse("g"), Parsed::Incomplete));
+        assert!(matches!(parse("gx"), Parsed::Invalid));
+        assert!(matches!(parse("dz"), Parsed::Invalid));
+        assert!(matches!(parse("Z"), Parsed::Invalid));
+        assert!(matches!(
+            parse("3dw"),
+            Parsed::Done((
+                Some(3),
+                Action::Operate('d', Target::Motion(Motion::WordStart))
+            ))
+        ));
+        assert!(matches!(
+            parse("2d3w"),
+            Parsed::Done((Some(6), Action::Operate('d', Target::Motion(_))))
+        ));
+        assert!(matches!(
+            parse("dd"),
+            Parsed::Done((None, Action::Operate('d', Target::Lines)))
+        ));
+        assert!(matches!(
+            parse("ci\""),
+            Parsed::Done((
+                None,
+                Action::Operate(
+                    'c',
+                    Target::Object {
+                        ch: '"',
+                        around: false
+                    }
+                )
+            ))
+        ));
+        assert!(matches!(
+            parse("dtx"),
+            Parsed::Done((
+                None,
+                Action::Operate(
+                    'd',
+                    Target::Motion(Motion::Find {
+                        ch: 'x',
+                        before: true,
+                        backward: false
+                    })
+                )
+            ))
+        ));
+        assert!(matches!(
+            parse("0"),
+            Parsed::Done((None, Action::Move(Motion::LineStart)))
+        ));
+        assert!(matches!(
+            parse("gg"),
+            Parsed::Done((None, Action::Move(Motion::FirstLine)))
+        ));
+        assert!(matches!(
+            parse("A"),
+            Parsed::Done((None, Action::Insert('A')))
+        ));
+        assert!(matches!(
+            parse_visual("d"),
+            Parsed::Done((None, Action::Operate('d', Target::Selection)))
+        ));
+        assert!(matches!(
+            parse_visual("2w"),
+            Parsed::Done((Some(2), Action::Move(Motion::WordStart)))
+        ));
+        assert!(matches!(
+            parse_visual("o"),
+            Parsed::Done((None, Action::SwapEnds))
+        ));
+    }
+
+    #[test]
+    fn moves_by_words_and_lines() {
+        let mut output = output(&["foo bar.baz  qux", "", "  second line", "last"]);
+        for (keys, expected) in [
+            ("w", (4, 0)),
+            ("w", (7, 0)),
+            ("e", (10, 0)),
+            ("w", (13, 0)),
+            ("w", (0, 1)),
+            ("w", (2, 2)),
+            ("b", (0, 1)),
+            ("b", (13, 0)),
+            ("$", (15, 0)),
+            ("0", (0, 0)),
+            ("fb", (4, 0)),
+            ("2fa", (9, 0)),
+            ("2Tb", (5, 0)),
+            ("G", (0, 3)),
+            ("3G", (2, 2)),
+            ("gg", (0, 0)),
+            ("2j", (0, 2)),
+            ("l$j", (3, 3)),
+            ("99k", (3, 0)),
+        ] {
+            press(&mut output, keys);
+            assert_eq!(position(&output), expected, "{}", keys);
+        }
+    }
+
+    #[test]
+    fn operators_work_on_motions_and_repeat() {
+        let mut output = output(&["foo bar baz", "two", "three", "four"]);
+        press(&mut output, "dw");
+        assert_eq!(rows(&output)[0], "bar baz");
+        press(&mut output, ".");
+        assert_eq!(rows(&output)[0], "baz");
+        press(&mut output, "u");
+        assert_eq!(rows(&output)[0], "bar baz");
+        press(&mut output, "cwxy\x1b");
+        assert_eq!(rows(&output)[0], "xy baz");
+        assert_eq!(position(&output), (1, 0));
+        press(&mut output, "w.");
+        assert_eq!(rows(&output)[0], "xy xy");
+        press(&mut output, "dd");
+        assert_eq!(rows(&output), ["two", "three", "four"]);
+        press(&mut output, "p");
+        assert_eq!(rows(&output), ["two", "xy xy", "three", "four"]);
+        press(&mut output, "2dd");
+        assert_eq!(rows(&output), ["two", "four"]);
+        press(&mut output, "x");
+        assert_eq!(rows(&output)[1], "our");
+    }
+
+    #[test]
+    fn text_objects() {
+        let mut output = output(&[
+            "call(a, (b), \"x y\")  word  end",
+            "fn f() {",
+            "    body",
+            "}",
+        ]);
+        press(&mut output, "fbdi(");
+        assert_eq!(rows(&output)[0], "call(a, (), \"x y\")  word  end");
+        press(&mut output, "0f,di(");
+        assert_eq!(rows(&output)[0], "call()  word  end");
+        press(&mut output, "u0f\"ci\"z\x1b");
+        assert_eq!(rows(&output)[0], "call(a, (), \"z\")  word  end");
+        press(&mut output, "fwdaw");
+        assert_eq!(rows(&output)[0], "call(a, (), \"z\")  end");
+        press(&mut output, "jjdiB");
+        assert_eq!(rows(&output)[1..], ["fn f() {", "}"]);
+        // no bracket around the cursor changes nothing
+        press(&mut output, "ggdi[");
+        assert_eq!(rows(&output)[0], "call(a, (), \"z\")  end");
+    }
+
+    #[test]
+    fn visual_mode_selects_inclusively() {
+        let mut output = output(&["one two three", "four", "five"]);
+        press(&mut output, "wve");
+        assert_eq!(output.visual_selection(), Some(((4, 0), (7, 0))));
+        press(&mut output, "d");
+        assert_eq!(rows(&output)[0], "one  three");
+        assert!(output.cursor_controller.anchor.is_none());
+        press(&mut output, "Vj");
+        assert_eq!(output.visual_selection(), Some(((0, 0), (4, 1))));
+        press(&mut output, "y");
+        assert_eq!(output.clipboard.last().unwrap().text, "one  three\nfour\n");
+    }
+
+    #[test]
+    fn commands_on_the_line_past_the_end_do_not_panic() {
+        // where PageDown or a click below the text leaves the cursor
+        let past_the_end = |output: &mut Output| {
+            let y = output.editor_rows.number_of_rows();
+            output.cursor_controller.set_position((0, y));
+        };
+        for keys in ["x", "dd", "D", "diw", "di(", "p", "J", "."] {
+            let mut output = output(&["one", "two"]);
+            past_the_end(&mut output);
+            press(&mut output, keys);
+            assert!(position(&output).1 < 2, "{}", keys);
+        }
+        let mut output = output(&["one", "two"]);
+        past_the_end(&mut output);
+        press(&mut output, "v");
+        assert_eq!(output.visual_selection(), Some(((0, 1), (1, 1))));
+        // a click below the text while selecting
+        past_the_end(&mut output);
+        output.cursor_controller.anchor = Some((1, 0));
+        assert_eq!(output.visual_selection(), Some(((1, 0), (3, 1))));
+        assert_eq!(output.selected_columns(1), 0..3);
+        let mut output = super::tests::output(&[]);
+        press(&mut output, "vxddp");
+        assert!(rows(&output).len() <= 1);
+    }
+}
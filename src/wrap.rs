@@ -0,0 +1,243 @@
+use crate::{get_render_x, grapheme_width, CursorController, EditorRows, Output};
+use unicode_segmentation::UnicodeSegmentation;
+
+/* soft wrapping: a row wider than the screen continues on the next screen lines. Every
+screen line starts at a grapheme boundary, so wide characters are never split */
+
+// the render column each screen line of `render` starts at, wrapped at `width` columns
+pub fn wrap_columns(render: &str, width: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut column = 0;
+    for grapheme in render.graphemes(true) {
+        let line_start = starts[starts.len() - 1];
+        let grapheme_width = grapheme_width(grapheme);
+        if column > line_start && column + grapheme_width > line_start + width {
+            starts.push(column);
+        }
+        column += grapheme_width;
+    }
+    starts
+}
+
+// the screen line in `starts` that holds `render_x`
+fn line_of(starts: &[usize], render_x: usize) -> usize {
+    starts
+        .iter()
+        .rposition(|start| *start <= render_x)
+        .unwrap_or(0)
+}
+
+// the render column `column` columns into screen line `line`, kept on that line
+fn column_in_line(starts: &[usize], line: usize, column: usize) -> usize {
+    match starts.get(line + 1) {
+        Some(next) => (starts[line] + column).min(next - 1),
+        None => starts[line] + column,
+    }
+}
+
+impl EditorRows {
+    // the line past the end, where a new row is typed, takes one screen line too
+    fn wrap_row(&self, at: usize, width: usize) -> Vec<usize> {
+        if at < self.number_of_rows() {
+            wrap_columns(&Self::render_row(&self.get_row(at)), width)
+        } else {
+            vec![0]
+        }
+    }
+}
+
+impl CursorController {
+    fn wrap_width(&self) -> usize {
+        self.screen_columns.max(1)
+    }
+
+    // screen lines from `from` down to `to`, both (row, screen line) pairs, at most `limit`
+    fn lines_between(
+        &self,
+        editor_rows: &EditorRows,
+        from: (usize, usize),
+        to: (usize, usize),
+        limit: usize,
+    ) -> usize {
+        if to <= from {
+            return 0;
+        }
+        let mut lines = 0;
+        let (mut y, mut line) = from;
+        while y < to.0 && lines < limit {
+            lines += editor_rows.wrap_row(y, self.wrap_width()).len() - line;
+            y += 1;
+            line = 0;
+        }
+        if y == to.0 {
+            lines += to.1.saturating_sub(line);
+        }
+        lines.min(limit)
+    }
+
+    // keeps the cursor's screen line on screen, counting the lines wrapped rows take up
+    pub(crate) fn scroll_wrapped(&mut self, editor_rows: &EditorRows) {
+        self.column_offset = 0;
+        let width = self.wrap_width();
+        let starts = editor_rows.wrap_row(self.cursor_y, width);
+        let cursor = (self.cursor_y, line_of(&starts, self.render_x));
+        self.line_offset = self
+            .line_offset
+            .min(editor_rows.wrap_row(self.row_offset, width).len() - 1);
+        if cursor < (self.row_offset, self.line_offset) {
+            (self.row_offset, self.line_offset) = cursor;
+            return;
+        }
+        let screen_rows = self.screen_rows.max(1);
+        let top = (self.row_offset, self.line_offset);
+        if self.lines_between(editor_rows, top, cursor, screen_rows) < screen_rows {
+            return;
+        }
+        // walks back up from the cursor until it sits on the last line of the screen
+        let (mut y, mut line) = cursor;
+        let mut remaining = screen_rows - 1;
+        while remaining > 0 {
+            if line >= remaining {
+                line -= remaining;
+                break;
+            }
+            remaining -= line + 1;
+            if y == 0 {
+                line = 0;
+                break;
+            }
+            y -= 1;
+            line = editor_rows.wrap_row(y, width).len() - 1;
+        }
+        (self.row_offset, self.line_offset) = (y, line);
+    }
+
+    /* where the cursor is drawn, relative to the top left of the text area. None when the
+    view was scrolled away from it */
+    pub(crate) fn wrapped_screen_position(
+        &self,
+        editor_rows: &EditorRows,
+    ) -> Option<(usize, usize)> {
+        let width = self.wrap_width();
+        let starts = editor_rows.wrap_row(self.cursor_y, width);
+        let cursor = (self.cursor_y, line_of(&starts, self.render_x));
+        let top = (self.row_offset, self.line_offset);
+        if cursor < top {
+            return None;
+        }
+        let y = self.lines_between(editor_rows, top, cursor, self.screen_rows);
+        // the end of a row that fills its last line exactly stays on that line
+        let x = (self.render_x - starts[cursor.1]).min(width - 1);
+        (y < self.screen_rows).then_some((x, y))
+    }
+
+    // the row and screen line `lines` screen lines below the top of the view
+    fn line_below_top(&self, editor_rows: &EditorRows, lines: usize) -> (usize, usize) {
+        let (mut y, mut line) = (self.row_offset, self.line_offset);
+        for _ in 0..lines {
+            if line + 1 < editor_rows.wrap_row(y, self.wrap_width()).len() {
+                line += 1;
+            } else if y < editor_rows.number_of_rows() {
+                y += 1;
+                line = 0;
+            }
+        }
+        (y, line)
+    }
+
+    // the buffer position drawn at `column` of screen line `row`
+    pub(crate) fn wrapped_position_at(
+        &self,
+        editor_rows: &EditorRows,
+        column: usize,
+        row: usize,
+    ) -> (usize, usize) {
+        let (y, line) = self.line_below_top(editor_rows, row);
+        let starts = editor_rows.wrap_row(y, self.wrap_width());
+        (
+            editor_rows.column_to_x(y, column_in_line(&starts, line, column)),
+            y,
+        )
+    }
+
+    // moves the view `lines` screen lines up or down, the cursor stays where it is
+    pub(crate) fn scroll_wrapped_view(&mut self, editor_rows: &EditorRows, up: bool, lines: usize) {
+        if !up {
+            let (y, line) = self.line_below_top(editor_rows, lines);
+            // the last row stays on screen
+            let last = editor_rows.number_of_rows().saturating_sub(1);
+            (self.row_offset, self.line_offset) = if y > last {
+                (
+                    last,
+                    editor_rows.wrap_row(last, self.wrap_width()).len() - 1,
+                )
+            } else {
+                (y, line)
+            };
+            return;
+        }
+        for _ in 0..lines {
+            if self.line_offset > 0 {
+                self.line_offset -= 1;
+            } else if self.row_offset > 0 {
+                self.row_offset -= 1;
+                self.line_offset = editor_rows
+                    .wrap_row(self.row_offset, self.wrap_width())
+                    .len()
+                    - 1;
+            }
+        }
+    }
+
+    // Up and Down by screen line, keeping the column within the line
+    pub(crate) fn move_screen_line(&mut self, up: bool, editor_rows: &EditorRows) {
+        let width = self.wrap_width();
+        let number_of_rows = editor_rows.number_of_rows();
+        let starts = editor_rows.wrap_row(self.cursor_y, width);
+        let render_x = if self.cursor_y < number_of_rows {
+            get_render_x(&editor_rows.get_row(self.cursor_y), self.cursor_x)
+        } else {
+            0
+        };
+        let line = line_of(&starts, render_x);
+        let column = render_x - starts[line];
+        let (y, line_starts, line) = if up && line > 0 {
+            (self.cursor_y, starts, line - 1)
+        } else if up && self.cursor_y > 0 {
+            let starts = editor_rows.wrap_row(self.cursor_y - 1, width);
+            let last = starts.len() - 1;
+            (self.cursor_y - 1, starts, last)
+        } else if !up && line + 1 < starts.len() {
+            (self.cursor_y, starts, line + 1)
+        } else if !up && self.cursor_y < number_of_rows {
+            (
+                self.cursor_y + 1,
+                editor_rows.wrap_row(self.cursor_y + 1, width),
+                0,
+            )
+        } else {
+            return;
+        };
+        // a shorter line puts the cursor on its last grapheme, not on the next line
+        let target = column_in_line(&line_starts, line, column);
+        self.cursor_y = y;
+        self.cursor_x = editor_rows.column_to_x(y, target);
+    }
+}
+
+impl Output {
+    pub(crate) fn toggle_soft_wrap(&mut self) {
+        let cursor_controller = &mut self.cursor_controller;
+        cursor_controller.soft_wrap = !cursor_controller.soft_wrap;
+        cursor_controller.line_offset = 0;
+        cursor_controller.column_offset = 0;
+        self.status_message.set_message(format!(
+            "Soft wrap {}",
+            if cursor_controller.soft_wrap {
+                "on"
+            } else {
+                "off"
+            }
+        ));
+    }
+}